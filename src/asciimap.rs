@@ -0,0 +1,105 @@
+//! Render maps and rooms as plain text.
+//!
+//! Used to inspect generated dungeons without starting the game.  North
+//! is always on top, so the room rows are printed from the highest y
+//! coordinate to the lowest while the map rows are printed from the lowest
+//! to the highest.
+
+use crate::map::{Coordinate, Map, RoomConnections};
+use crate::room::{Room, RoomField, RoomRole};
+use std::fmt::Write;
+
+/// Character which represents the field in a room dump.
+pub fn field_char(field: RoomField) -> char {
+    match field {
+        RoomField::Nothing => '.',
+        RoomField::Wall => '#',
+        RoomField::Stone => 's',
+        RoomField::Bush => 'b',
+        RoomField::Player => '@',
+        RoomField::Enemy => 'e',
+        RoomField::Exit(_) => 'E',
+    }
+}
+
+/// Character which marks the role of a room in the overview.
+pub fn role_char(role: RoomRole) -> char {
+    match role {
+        RoomRole::Normal => ' ',
+        RoomRole::Start => '*',
+        RoomRole::Boss => 'B',
+        RoomRole::Treasure => 'T',
+        RoomRole::Shop => '$',
+    }
+}
+
+/// Draw the rooms of the map as a grid.
+///
+/// Each room is drawn as `[ ]` with the character of its role inside,
+/// see `role_char`.  Exits between neighbor rooms are drawn as `-` and `|`.
+pub fn render_map_overview(map: &Map<Room>) -> String {
+    let coordinates: Vec<Coordinate> = map.iter().map(|(coordinate, _)| coordinate).collect();
+    if coordinates.is_empty() {
+        return String::new();
+    }
+    let min_x = coordinates.iter().map(|(x, _)| *x).min().unwrap();
+    let max_x = coordinates.iter().map(|(x, _)| *x).max().unwrap();
+    let min_y = coordinates.iter().map(|(_, y)| *y).min().unwrap();
+    let max_y = coordinates.iter().map(|(_, y)| *y).max().unwrap();
+    let columns = (max_x - min_x + 1) as usize * 4 - 1;
+    let rows = (max_y - min_y + 1) as usize * 2 - 1;
+    let mut canvas = vec![vec![' '; columns]; rows];
+
+    for (coordinate, room) in map.iter() {
+        let column = (coordinate.0 - min_x) as usize * 4;
+        let row = (coordinate.1 - min_y) as usize * 2;
+        canvas[row][column] = '[';
+        canvas[row][column + 1] = role_char(room.role);
+        canvas[row][column + 2] = ']';
+
+        let targets = room.exit_targets(coordinate);
+        if targets.contains(&(coordinate.0 + 1, coordinate.1)) && column + 3 < columns {
+            canvas[row][column + 3] = '-';
+        }
+        if targets.contains(&(coordinate.0, coordinate.1 + 1)) && row + 1 < rows {
+            canvas[row + 1][column + 1] = '|';
+        }
+    }
+
+    let mut result = String::new();
+    for row in canvas {
+        let line: String = row.into_iter().collect();
+        result.push_str(line.trim_end());
+        result.push('\n');
+    }
+    result
+}
+
+/// List every room with its role and the rooms its exits lead to.
+pub fn render_room_list(map: &Map<Room>) -> String {
+    let mut result = String::new();
+    for (coordinate, room) in map.iter() {
+        let mut targets = room.exit_targets(coordinate);
+        targets.sort();
+        let targets: Vec<String> = targets
+            .into_iter()
+            .map(|target| format!("{:?}", target))
+            .collect();
+        writeln!(result, "{:?} {:?} -> {}", coordinate, room.role, targets.join(", ")).unwrap();
+    }
+    result
+}
+
+/// Dump the fields of a room with one character per field.
+pub fn render_room(room: &Room) -> String {
+    let mut result = String::new();
+    for y in (0..room.height).rev() {
+        for x in 0..room.width {
+            if let Some(field) = room.get_field(x, y) {
+                result.push(field_char(field));
+            }
+        }
+        result.push('\n');
+    }
+    result
+}
@@ -0,0 +1,579 @@
+//! Data-driven behavior of enemies.
+//!
+//! Every enemy type in `resources/enemies.ron` defines a small state
+//! machine.  An enemy is in one `BehaviorState` at a time and switches to
+//! another one by the first matching `Transition` of its type, for example
+//! from wandering around to chasing the player once the player comes into
+//! sight.  The `BehaviorSystem` turns the state into a velocity and updates
+//! the `CharacterMeta`, so enemies are animated exactly like the player.
+//!
+//! Chasing and attacking enemies follow a `FlowField` to the player, so
+//! they walk around walls, stones and bushes.
+//!
+//! The system only depends on resources of the gameplay, so it also runs
+//! in the headless mode.
+
+use amethyst::{
+    config::Config,
+    core::{timing::Time, Transform},
+    ecs::{Component, DenseVecStorage, Join, Read, ReadStorage, System, Write, WriteStorage},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use specs_physics::nphysics::algebra::Velocity3;
+use specs_physics::PhysicsBody;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::charactermeta::{CharacterDirection, CharacterMeta};
+use crate::charactermove::UserMove;
+use crate::gamerng::GameRng;
+use crate::geometry::WorldGeometry;
+use crate::health::{Health, Knockback};
+use crate::pathfinding::NavigationGrid;
+use crate::room::RoomRole;
+use crate::visibility::line_of_sight_with;
+
+/// What an enemy is currently doing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum BehaviorState {
+    /// Stand still.
+    Idle,
+    /// Walk into random directions.
+    Wander,
+    /// Walk along the `patrol` waypoints of the enemy type.
+    Patrol,
+    /// Walk towards the player.
+    Chase,
+    /// Run away from the player.
+    Flee,
+    /// Charge at the player.
+    Attack,
+}
+
+/// Condition which triggers a transition.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    /// The player is closer than this many pixels and in sight.
+    PlayerWithin(f32),
+    /// The player is farther away than this many pixels or not in the room.
+    PlayerFartherThan(f32),
+    /// The health is below this fraction of the health of the enemy type.
+    HealthBelow(f32),
+    /// The enemy has been in the current state for this many seconds.
+    After(f32),
+}
+
+/// Switch from one state to another one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    /// States where the transition applies, all states if empty.
+    #[serde(default)]
+    pub from: Vec<BehaviorState>,
+    pub to: BehaviorState,
+    pub when: Condition,
+}
+
+/// Definition of an enemy type in the RON file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnemyType {
+    /// Name of the character sprites.
+    pub sprite: String,
+    pub health: f32,
+    /// Speed in pixels per second.
+    pub speed: f32,
+    /// Speed factor per state, states which are missing use 1.0.
+    pub speed_factors: BTreeMap<BehaviorState, f32>,
+    /// Room roles where this type appears, all roles if empty.
+    pub roles: Vec<RoomRole>,
+    pub initial_state: BehaviorState,
+    /// Seconds until a wandering enemy picks a new direction.
+    pub wander_interval: f32,
+    /// Waypoints in tiles relative to the spawn position.
+    pub patrol: Vec<(i32, i32)>,
+    /// Checked in order, the first matching transition is taken.
+    pub transitions: Vec<Transition>,
+}
+
+impl Default for EnemyType {
+    /// Enemy which always chases the player.
+    fn default() -> Self {
+        EnemyType {
+            sprite: "healer".to_string(),
+            health: 1.0,
+            speed: 64.0,
+            speed_factors: BTreeMap::new(),
+            roles: Vec::new(),
+            initial_state: BehaviorState::Chase,
+            wander_interval: 1.0,
+            patrol: Vec::new(),
+            transitions: Vec::new(),
+        }
+    }
+}
+
+impl EnemyType {
+    /// Walking speed in the given state.
+    pub fn speed(&self, state: BehaviorState) -> f32 {
+        self.speed * self.speed_factors.get(&state).copied().unwrap_or(1.0)
+    }
+
+    /// Find the state the enemy switches to, if any.
+    pub fn next_state(
+        &self,
+        behavior: &Behavior,
+        player_distance: Option<f32>,
+        player_visible: bool,
+        health: Option<f32>,
+    ) -> Option<BehaviorState> {
+        self.transitions
+            .iter()
+            .filter(|transition| transition.to != behavior.state)
+            .filter(|transition| transition.from.is_empty() || transition.from.contains(&behavior.state))
+            .find(|transition| match transition.when {
+                Condition::PlayerWithin(distance) => {
+                    player_visible
+                        && player_distance.map_or(false, |player_distance| player_distance < distance)
+                }
+                Condition::PlayerFartherThan(distance) => {
+                    player_distance.map_or(true, |player_distance| player_distance > distance)
+                }
+                Condition::HealthBelow(fraction) => {
+                    health.map_or(false, |health| health < self.health * fraction)
+                }
+                Condition::After(seconds) => behavior.time_in_state >= seconds,
+            })
+            .map(|transition| transition.to)
+    }
+}
+
+/// RON file definition.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnemyData {
+    pub enemies: BTreeMap<String, EnemyType>,
+}
+
+/// Resource with all enemy types.
+#[derive(Clone, Debug, Default)]
+pub struct EnemyLibrary {
+    pub enemies: BTreeMap<String, EnemyType>,
+    /// Used if no enemy type fits.
+    pub fallback: EnemyType,
+}
+
+impl EnemyLibrary {
+    pub fn new(data: EnemyData) -> Self {
+        EnemyLibrary {
+            enemies: data.enemies,
+            fallback: EnemyType::default(),
+        }
+    }
+
+    /// Enemy type with the given name or the fallback.
+    pub fn get(&self, name: &str) -> &EnemyType {
+        self.enemies.get(name).unwrap_or(&self.fallback)
+    }
+
+    /// Pick the enemy type for an enemy field in a room.
+    ///
+    /// The choice only depends on the role and the tile, so an enemy has
+    /// the same type whenever the room is entered.
+    pub fn choose(&self, role: RoomRole, (x, y): (usize, usize)) -> (&str, &EnemyType) {
+        let candidates: Vec<_> = self
+            .enemies
+            .iter()
+            .filter(|(_, enemy_type)| enemy_type.roles.is_empty() || enemy_type.roles.contains(&role))
+            .collect();
+        if candidates.is_empty() {
+            return ("", &self.fallback);
+        }
+        let (name, enemy_type) = candidates[(x * 7 + y * 13) % candidates.len()];
+        (name.as_str(), enemy_type)
+    }
+}
+
+/// Read the enemy types from a RON file.
+///
+/// Errors are logged and only the fallback enemy is available then.
+pub fn load_enemies(path: impl AsRef<Path>) -> EnemyLibrary {
+    let path = path.as_ref();
+    match EnemyData::load(path) {
+        Ok(data) => EnemyLibrary::new(data),
+        Err(error) => {
+            warn!("Couldn't load enemies from {}: {}", path.display(), error);
+            EnemyLibrary::default()
+        }
+    }
+}
+
+/// State of the behavior of an enemy.
+pub struct Behavior {
+    /// Name of the enemy type in the `EnemyLibrary`.
+    pub enemy_type: String,
+    pub state: BehaviorState,
+    pub time_in_state: f32,
+    /// Pixel position where the enemy was spawned, patrols are relative to it.
+    pub home: (f32, f32),
+    /// Index of the next patrol waypoint.
+    pub waypoint: usize,
+    pub wander_direction: (f32, f32),
+    /// Seconds until the next wander direction is picked.
+    pub wander_time: f32,
+}
+
+impl Behavior {
+    pub fn new(enemy_type: &str, state: BehaviorState, home: (f32, f32)) -> Self {
+        Behavior {
+            enemy_type: enemy_type.to_string(),
+            state,
+            time_in_state: 0.0,
+            home,
+            waypoint: 0,
+            wander_direction: (0.0, 0.0),
+            wander_time: 0.0,
+        }
+    }
+
+    /// Switch to another state and start it from the beginning.
+    pub fn enter(&mut self, state: BehaviorState) {
+        self.state = state;
+        self.time_in_state = 0.0;
+        self.wander_time = 0.0;
+    }
+}
+
+impl Component for Behavior {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Let a character walk in the given direction.
+///
+/// Sets the velocity and the `CharacterMeta`, so the character is animated
+/// like the player.  If the direction is zero, the character stops.
+pub fn walk(
+    physics_body: &mut PhysicsBody<f32>,
+    character_meta: &mut CharacterMeta,
+    (direction_x, direction_y): (f32, f32),
+    speed: f32,
+) {
+    let length = direction_x.hypot(direction_y);
+    if length < 0.001 || speed <= 0.0 {
+        character_meta.moving = false;
+        physics_body.velocity = Velocity3::linear(0.0, 0.0, 0.0);
+        return;
+    }
+    character_meta.moving = true;
+    character_meta.direction = if direction_x.abs() > direction_y.abs() {
+        if direction_x > 0.0 {
+            CharacterDirection::Right
+        } else {
+            CharacterDirection::Left
+        }
+    } else if direction_y > 0.0 {
+        CharacterDirection::Up
+    } else {
+        CharacterDirection::Down
+    };
+    physics_body.velocity = Velocity3::linear(
+        direction_x / length * speed,
+        direction_y / length * speed,
+        0.0,
+    );
+}
+
+/// Pixels a patrolling enemy must come close to a waypoint.
+const WAYPOINT_DISTANCE: f32 = 4.0;
+
+/// Runs the state machines of the enemies and lets them walk.
+#[derive(Default)]
+pub struct BehaviorSystem;
+
+impl<'s> System<'s> for BehaviorSystem {
+    type SystemData = (
+        Read<'s, Time>,
+        Read<'s, EnemyLibrary>,
+        Read<'s, NavigationGrid>,
+        Read<'s, WorldGeometry>,
+        Write<'s, GameRng>,
+        WriteStorage<'s, Behavior>,
+        ReadStorage<'s, UserMove>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, Health>,
+        ReadStorage<'s, Knockback>,
+        WriteStorage<'s, PhysicsBody<f32>>,
+        WriteStorage<'s, CharacterMeta>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            time,
+            enemy_library,
+            navigation_grid,
+            geometry,
+            mut rng,
+            mut behaviors,
+            user_moves,
+            transforms,
+            healths,
+            knockbacks,
+            mut physics_bodies,
+            mut character_metas,
+        ): Self::SystemData,
+    ) {
+        let delta = time.delta_seconds();
+        let player = (&user_moves, &transforms)
+            .join()
+            .next()
+            .map(|(_, transform)| (transform.translation().x, transform.translation().y));
+        let flow_field = player.map(|position| navigation_grid.flow_field(geometry.pixel_to_tile(position)));
+
+        for (behavior, transform, physics_body, character_meta, health, knockback) in (
+            &mut behaviors,
+            &transforms,
+            &mut physics_bodies,
+            &mut character_metas,
+            healths.maybe(),
+            knockbacks.maybe(),
+        )
+            .join()
+        {
+            let enemy_type = enemy_library.get(&behavior.enemy_type);
+            let position = (transform.translation().x, transform.translation().y);
+            let to_player = player.map(|(player_x, player_y)| (player_x - position.0, player_y - position.1));
+            let player_distance = to_player.map(|(x, y)| x.hypot(y));
+            let player_visible = player.map_or(false, |player| {
+                line_of_sight_with(
+                    |tile| !navigation_grid.is_passable(tile),
+                    geometry.pixel_to_tile(position),
+                    geometry.pixel_to_tile(player),
+                )
+            });
+
+            behavior.time_in_state += delta;
+            if let Some(state) = enemy_type.next_state(
+                behavior,
+                player_distance,
+                player_visible,
+                health.map(|health| health.health),
+            ) {
+                behavior.enter(state);
+            }
+
+            if let Some(knockback) = knockback {
+                physics_body.velocity =
+                    Velocity3::linear(knockback.velocity.0, knockback.velocity.1, 0.0);
+                continue;
+            }
+            if health.map_or(false, Health::is_dead) {
+                walk(physics_body, character_meta, (0.0, 0.0), 0.0);
+                continue;
+            }
+
+            let direction = match behavior.state {
+                BehaviorState::Idle => (0.0, 0.0),
+                BehaviorState::Wander => {
+                    behavior.wander_time -= delta;
+                    if behavior.wander_time <= 0.0 {
+                        behavior.wander_time = enemy_type.wander_interval;
+                        behavior.wander_direction = match rng.gen_range(0, 5) {
+                            0 => (1.0, 0.0),
+                            1 => (-1.0, 0.0),
+                            2 => (0.0, 1.0),
+                            3 => (0.0, -1.0),
+                            _ => (0.0, 0.0),
+                        };
+                    }
+                    behavior.wander_direction
+                }
+                BehaviorState::Patrol => {
+                    if enemy_type.patrol.is_empty() {
+                        (0.0, 0.0)
+                    } else {
+                        let home = behavior.home;
+                        let waypoint = |index: usize| {
+                            let (x, y) = enemy_type.patrol[index % enemy_type.patrol.len()];
+                            (
+                                home.0 + x as f32 * geometry.tile_size - position.0,
+                                home.1 + y as f32 * geometry.tile_size - position.1,
+                            )
+                        };
+                        let (x, y) = waypoint(behavior.waypoint);
+                        if x.hypot(y) < WAYPOINT_DISTANCE {
+                            behavior.waypoint = (behavior.waypoint + 1) % enemy_type.patrol.len();
+                            waypoint(behavior.waypoint)
+                        } else {
+                            (x, y)
+                        }
+                    }
+                }
+                BehaviorState::Chase | BehaviorState::Attack => {
+                    let next_tile = flow_field
+                        .as_ref()
+                        .and_then(|flow_field| flow_field.next_tile(geometry.pixel_to_tile(position)));
+                    match next_tile.map(|tile| geometry.tile_to_pixel(tile)) {
+                        Some((x, y)) => (x - position.0, y - position.1),
+                        // Already next to the player or there is no way.
+                        None => to_player.unwrap_or((0.0, 0.0)),
+                    }
+                }
+                BehaviorState::Flee => to_player.map_or((0.0, 0.0), |(x, y)| (-x, -y)),
+            };
+            walk(physics_body, character_meta, direction, enemy_type.speed(behavior.state));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::{Builder, Entity, RunNow, World, WorldExt};
+    use specs_physics::nphysics::object::BodyStatus;
+    use specs_physics::PhysicsBodyBuilder;
+
+    use crate::charactermeta::CharacterDirection;
+    use crate::room::{Room, RoomField};
+
+    /// World with the system set up and a library with the given enemy.
+    fn setup(enemy_type: EnemyType) -> (World, BehaviorSystem) {
+        let mut world = World::new();
+        let mut system = BehaviorSystem;
+        System::setup(&mut system, &mut world);
+        world.insert(GameRng::from_seed(1));
+        world.write_resource::<Time>().set_delta_seconds(0.1);
+        let mut enemies = BTreeMap::new();
+        enemies.insert("test".to_string(), enemy_type);
+        world.insert(EnemyLibrary::new(EnemyData { enemies }));
+        let geometry = WorldGeometry::default();
+        let room = Room::new(geometry.room_width, geometry.room_height);
+        world.insert(NavigationGrid::from_room(&room));
+        (world, system)
+    }
+
+    fn transform((x, y): (f32, f32)) -> Transform {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(x, y, 0.0);
+        transform
+    }
+
+    fn create_player(world: &mut World, position: (f32, f32)) -> Entity {
+        world.create_entity().with(UserMove).with(transform(position)).build()
+    }
+
+    fn create_enemy(world: &mut World, state: BehaviorState, position: (f32, f32), health: f32) -> Entity {
+        world
+            .create_entity()
+            .with(Behavior::new("test", state, position))
+            .with(transform(position))
+            .with(PhysicsBodyBuilder::<f32>::from(BodyStatus::Dynamic).build())
+            .with(CharacterMeta::new(CharacterDirection::Down))
+            .with(Health::new(health))
+            .build()
+    }
+
+    fn state(world: &World, enemy: Entity) -> BehaviorState {
+        world.read_storage::<Behavior>().get(enemy).unwrap().state
+    }
+
+    fn velocity(world: &World, enemy: Entity) -> (f32, f32) {
+        let physics_bodies = world.read_storage::<PhysicsBody<f32>>();
+        let velocity = physics_bodies.get(enemy).unwrap().velocity.linear;
+        (velocity.x, velocity.y)
+    }
+
+    #[test]
+    fn idle_enemy_chases_player_within_radius() {
+        let (mut world, mut system) = setup(EnemyType {
+            initial_state: BehaviorState::Idle,
+            transitions: vec![Transition {
+                from: vec![BehaviorState::Idle],
+                to: BehaviorState::Chase,
+                when: Condition::PlayerWithin(100.0),
+            }],
+            ..EnemyType::default()
+        });
+        let enemy = create_enemy(&mut world, BehaviorState::Idle, (100.0, 100.0), 1.0);
+        let player = create_player(&mut world, (300.0, 100.0));
+        system.run_now(&world);
+        assert_eq!(state(&world, enemy), BehaviorState::Idle);
+        assert_eq!(velocity(&world, enemy), (0.0, 0.0));
+
+        // The player comes closer.
+        world.write_storage::<Transform>().insert(player, transform((150.0, 100.0))).unwrap();
+        system.run_now(&world);
+        assert_eq!(state(&world, enemy), BehaviorState::Chase);
+        assert!(velocity(&world, enemy).0 > 0.0);
+    }
+
+    #[test]
+    fn enemy_doesnt_see_the_player_behind_a_stone() {
+        let (mut world, mut system) = setup(EnemyType {
+            initial_state: BehaviorState::Idle,
+            transitions: vec![Transition {
+                from: vec![BehaviorState::Idle],
+                to: BehaviorState::Chase,
+                when: Condition::PlayerWithin(200.0),
+            }],
+            ..EnemyType::default()
+        });
+        let geometry = WorldGeometry::default();
+        let enemy = create_enemy(&mut world, BehaviorState::Idle, geometry.tile_to_pixel((2, 3)), 1.0);
+        create_player(&mut world, geometry.tile_to_pixel((6, 3)));
+        world.write_resource::<NavigationGrid>().set_passable((4, 3), false);
+        system.run_now(&world);
+        assert_eq!(state(&world, enemy), BehaviorState::Idle);
+
+        // The stone is gone.
+        world.write_resource::<NavigationGrid>().set_passable((4, 3), true);
+        system.run_now(&world);
+        assert_eq!(state(&world, enemy), BehaviorState::Chase);
+    }
+
+    #[test]
+    fn enemy_flees_below_health() {
+        let (mut world, mut system) = setup(EnemyType {
+            health: 4.0,
+            transitions: vec![Transition {
+                from: Vec::new(),
+                to: BehaviorState::Flee,
+                when: Condition::HealthBelow(0.5),
+            }],
+            ..EnemyType::default()
+        });
+        let healthy = create_enemy(&mut world, BehaviorState::Chase, (100.0, 100.0), 3.0);
+        let hurt = create_enemy(&mut world, BehaviorState::Chase, (100.0, 200.0), 1.0);
+        create_player(&mut world, (200.0, 150.0));
+        system.run_now(&world);
+        assert_eq!(state(&world, healthy), BehaviorState::Chase);
+        assert_eq!(state(&world, hurt), BehaviorState::Flee);
+        let (x, y) = velocity(&world, hurt);
+        assert!(x < 0.0 && y > 0.0);
+    }
+
+    #[test]
+    fn patrol_walks_to_next_waypoint() {
+        let (mut world, mut system) = setup(EnemyType {
+            initial_state: BehaviorState::Patrol,
+            patrol: vec![(2, 0), (2, 2)],
+            ..EnemyType::default()
+        });
+        let tile_size = WorldGeometry::default().tile_size;
+        let enemy = create_enemy(&mut world, BehaviorState::Patrol, (100.0, 100.0), 1.0);
+        system.run_now(&world);
+        assert_eq!(world.read_storage::<Behavior>().get(enemy).unwrap().waypoint, 0);
+        let (x, y) = velocity(&world, enemy);
+        assert!(x > 0.0 && y.abs() < 0.001);
+
+        // Arrive at the first waypoint.
+        world
+            .write_storage::<Transform>()
+            .insert(enemy, transform((100.0 + 2.0 * tile_size, 100.0)))
+            .unwrap();
+        system.run_now(&world);
+        assert_eq!(world.read_storage::<Behavior>().get(enemy).unwrap().waypoint, 1);
+        let (x, y) = velocity(&world, enemy);
+        assert!(x.abs() < 0.001 && y > 0.0);
+    }
+}
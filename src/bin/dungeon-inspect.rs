@@ -0,0 +1,184 @@
+//! Print a dungeon as text without starting the game.
+//!
+//! Usage:
+//! ```text
+//! dungeon-inspect [--seed <number>] [--layout <name>] [--prefabs <file>] [--map <file>]
+//!                 [--room-size <width>x<height>] [--room <x>,<y>] [--overview]
+//! ```
+//!
+//! Without `--map` the dungeon is generated from the seed (random if not
+//! given) with the layout `random-walk`, `grid`, `bsp` or `linear`.
+//! Prefabs are only used if `--prefabs` is given.  Rooms have the size of
+//! the geometry in `resources/game_config.ron` unless `--room-size` is
+//! given.  `--room` only dumps the given room, `--overview` skips the room
+//! dumps entirely.
+
+extern crate amethyst;
+extern crate sprite_game;
+
+use amethyst::{config::Config, utils::application_root_dir};
+use sprite_game::{
+    asciimap, config::GameConfig, geometry::WorldGeometry, layout::LayoutConfig, map, mapfile,
+    prefab, room::MIN_ROOM_SIZE,
+};
+use std::process::exit;
+
+struct Arguments {
+    seed: Option<u64>,
+    layout: LayoutConfig,
+    prefab_file: Option<String>,
+    map_file: Option<String>,
+    /// Width and height of the generated rooms in tiles.
+    room_size: Option<(usize, usize)>,
+    room: Option<map::Coordinate>,
+    overview_only: bool,
+}
+
+fn parse_coordinate(value: &str) -> Option<map::Coordinate> {
+    let mut parts = value.splitn(2, ',');
+    let x = parts.next()?.trim().parse().ok()?;
+    let y = parts.next()?.trim().parse().ok()?;
+    Some((x, y))
+}
+
+fn parse_room_size(value: &str) -> Option<(usize, usize)> {
+    let mut parts = value.splitn(2, 'x');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    Some((width, height))
+}
+
+/// Geometry of the game configuration or the default if it can't be read.
+fn configured_geometry() -> WorldGeometry {
+    let path = match application_root_dir() {
+        Ok(app_root) => app_root.join("resources/game_config.ron"),
+        Err(error) => {
+            eprintln!("Couldn't find the application root, using the default geometry: {}", error);
+            return WorldGeometry::default();
+        }
+    };
+    match GameConfig::load(&path) {
+        Ok(config) => config.geometry,
+        Err(error) => {
+            eprintln!("Couldn't load {}, using the default geometry: {}", path.display(), error);
+            WorldGeometry::default()
+        }
+    }
+}
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut arguments = Arguments {
+        seed: None,
+        layout: LayoutConfig::default(),
+        prefab_file: None,
+        map_file: None,
+        room_size: None,
+        room: None,
+        overview_only: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("Missing value for --seed")?;
+                let seed = value
+                    .parse()
+                    .map_err(|error| format!("Invalid seed {}: {}", value, error))?;
+                arguments.seed = Some(seed);
+            }
+            "--layout" => {
+                let value = args.next().ok_or("Missing value for --layout")?;
+                arguments.layout = LayoutConfig::from_name(&value)
+                    .ok_or_else(|| format!("Unknown layout: {}", value))?;
+            }
+            "--prefabs" => {
+                arguments.prefab_file = Some(args.next().ok_or("Missing value for --prefabs")?);
+            }
+            "--map" => {
+                arguments.map_file = Some(args.next().ok_or("Missing value for --map")?);
+            }
+            "--room-size" => {
+                let value = args.next().ok_or("Missing value for --room-size")?;
+                let (width, height) = parse_room_size(&value)
+                    .ok_or_else(|| format!("Invalid room size {}, expected <width>x<height>", value))?;
+                if width < MIN_ROOM_SIZE || height < MIN_ROOM_SIZE {
+                    return Err(format!(
+                        "Rooms must be at least {}x{} tiles, got {}",
+                        MIN_ROOM_SIZE, MIN_ROOM_SIZE, value
+                    ));
+                }
+                arguments.room_size = Some((width, height));
+            }
+            "--room" => {
+                let value = args.next().ok_or("Missing value for --room")?;
+                let coordinate = parse_coordinate(&value)
+                    .ok_or_else(|| format!("Invalid room coordinate {}, expected x,y", value))?;
+                arguments.room = Some(coordinate);
+            }
+            "--overview" => arguments.overview_only = true,
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+    Ok(arguments)
+}
+
+fn main() {
+    let arguments = parse_arguments().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        exit(2);
+    });
+
+    let map = if let Some(map_file) = &arguments.map_file {
+        mapfile::load_map(map_file).unwrap_or_else(|error| {
+            eprintln!("Couldn't load map: {}", error);
+            exit(1);
+        })
+    } else {
+        let seed = arguments.seed.unwrap_or_else(rand::random);
+        println!("Seed: {}", seed);
+        let (room_width, room_height) = arguments.room_size.unwrap_or_else(|| {
+            let geometry = configured_geometry();
+            (geometry.room_width, geometry.room_height)
+        });
+        let prefabs = arguments
+            .prefab_file
+            .as_ref()
+            .map(prefab::load_prefabs)
+            .unwrap_or_default();
+        map::generate_dungeon(
+            seed,
+            arguments.layout.generator().as_ref(),
+            &prefabs,
+            room_width,
+            room_height,
+        )
+    };
+
+    println!("Rooms: {}", map.len());
+    print!("{}", asciimap::render_map_overview(&map));
+    println!();
+    print!("{}", asciimap::render_room_list(&map));
+    if let Err(errors) = map.validate() {
+        println!();
+        for error in errors {
+            println!("Invalid: {}", error);
+        }
+    }
+    if arguments.overview_only {
+        return;
+    }
+
+    for (coordinate, room) in map.iter() {
+        if arguments.room.map_or(true, |selected| selected == coordinate) {
+            println!();
+            println!("Room {:?}", coordinate);
+            print!("{}", asciimap::render_room(room));
+        }
+    }
+    if let Some(selected) = arguments.room {
+        if !map.has_room(selected) {
+            eprintln!("There is no room at {:?}", selected);
+            exit(1);
+        }
+    }
+}
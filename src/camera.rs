@@ -0,0 +1,140 @@
+//! Camera which follows the player through rooms larger than the screen.
+//!
+//! The camera only moves once the player leaves the dead zone around the
+//! center of the view and then glides after the player.  It never shows
+//! anything outside of the current room, rooms which are smaller than the
+//! view are centered.
+
+use amethyst::{
+    core::{timing::Time, Transform},
+    ecs::{Component, DenseVecStorage, Join, Read, ReadStorage, System, WriteStorage},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::charactermove::UserMove;
+
+/// Camera settings in the `GameConfig`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    /// Width of the visible area in pixels.
+    pub view_width: f32,
+    /// Height of the visible area in pixels.
+    pub view_height: f32,
+    /// Width of the area around the center where the player can move
+    /// without moving the camera.
+    pub dead_zone_width: f32,
+    /// Height of the dead zone.
+    pub dead_zone_height: f32,
+    /// Seconds the camera needs to catch up about two thirds of the
+    /// distance to the player, 0 follows immediately.
+    pub smoothing: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            view_width: 640.0,
+            view_height: 480.0,
+            dead_zone_width: 128.0,
+            dead_zone_height: 96.0,
+            smoothing: 0.15,
+        }
+    }
+}
+
+/// Size of the current room in pixels.
+///
+/// Inserted when the room is populated.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RoomBounds {
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Lets a camera follow the player.
+pub struct CameraFollow {
+    pub config: CameraConfig,
+    /// Jump to the player without smoothing, used when a room is entered.
+    pub snap: bool,
+}
+
+impl CameraFollow {
+    pub fn new(config: CameraConfig) -> Self {
+        CameraFollow { config, snap: true }
+    }
+}
+
+impl Component for CameraFollow {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Moves the cameras with `CameraFollow` after the `UserMove` entity.
+pub struct CameraFollowSystem;
+
+impl<'s> System<'s> for CameraFollowSystem {
+    type SystemData = (
+        Read<'s, Time>,
+        Read<'s, RoomBounds>,
+        ReadStorage<'s, UserMove>,
+        WriteStorage<'s, CameraFollow>,
+        WriteStorage<'s, Transform>,
+    );
+
+    fn run(&mut self, (time, room_bounds, user_moves, mut camera_follows, mut transforms): Self::SystemData) {
+        let player = (&user_moves, &transforms)
+            .join()
+            .next()
+            .map(|(_, transform)| (transform.translation().x, transform.translation().y));
+        let player = match player {
+            Some(player) => player,
+            None => return,
+        };
+
+        for (camera_follow, transform) in (&mut camera_follows, &mut transforms).join() {
+            let config = camera_follow.config;
+            let current = (transform.translation().x, transform.translation().y);
+            let (target_x, target_y, factor) = if camera_follow.snap {
+                (player.0, player.1, 1.0)
+            } else {
+                let factor = if config.smoothing <= 0.0 {
+                    1.0
+                } else {
+                    1.0 - (-time.delta_seconds() / config.smoothing).exp()
+                };
+                (
+                    follow(current.0, player.0, config.dead_zone_width / 2.0),
+                    follow(current.1, player.1, config.dead_zone_height / 2.0),
+                    factor,
+                )
+            };
+            let target = (
+                clamp_to_room(target_x, config.view_width, room_bounds.width),
+                clamp_to_room(target_y, config.view_height, room_bounds.height),
+            );
+            camera_follow.snap = false;
+            transform.set_translation_x(current.0 + (target.0 - current.0) * factor);
+            transform.set_translation_y(current.1 + (target.1 - current.1) * factor);
+        }
+    }
+}
+
+/// Camera position on one axis which keeps the player in the dead zone.
+fn follow(camera: f32, player: f32, half_dead_zone: f32) -> f32 {
+    if player > camera + half_dead_zone {
+        player - half_dead_zone
+    } else if player < camera - half_dead_zone {
+        player + half_dead_zone
+    } else {
+        camera
+    }
+}
+
+/// Keep the view inside the room on one axis.
+fn clamp_to_room(center: f32, view: f32, room: f32) -> f32 {
+    if room <= view {
+        room / 2.0
+    } else {
+        center.max(view / 2.0).min(room - view / 2.0)
+    }
+}
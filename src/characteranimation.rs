@@ -1,58 +1,377 @@
-//! ECS to set required animations
+//! Data-driven animations of characters.
 //!
+//! Every character type in `resources/character_animations.ron` defines a
+//! small state machine.  A character is in one `AnimationState` at a time,
+//! each state shows a clip with one animation per direction, like
+//! `healer_walk_up`.  The first matching `AnimationTransition` switches to
+//! another state, for example when the `CharacterMeta` starts moving, the
+//! character is hurt or a clip which plays once has finished.  A dying
+//! character stays until its die clip has played.
 //!
+//! States whose clips are not in the atlases are left out, so characters
+//! without attack sprites simply keep walking.
 
-use amethyst::ecs::{Component, DenseVecStorage};
-use amethyst::ecs::{Join, ReadStorage, System, WriteStorage};
+use amethyst::{
+    config::Config,
+    core::shrev::{EventChannel, ReaderId},
+    ecs::{Component, DenseVecStorage, Entities, Join, ReadStorage, System, Write, WriteStorage},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
 
 use crate::charactermeta::CharacterDirection;
 use crate::charactermeta::CharacterMeta;
-use crate::spriteanimation::SpriteAnimation;
+use crate::health::{Health, Knockback};
+use crate::spriteanimation::{AnimationFinishedEvent, PlaybackMode, SpriteAnimation};
+use crate::spriteanimationloader::{AnimationDefinition, SpriteAnimationStore};
 
-/// Component which contains the sprite animations.
+/// What the animation of a character currently shows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum AnimationState {
+    Idle,
+    Walk,
+    Attack,
+    Hurt,
+    Die,
+}
+
+/// Reason to switch to another state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnimationTrigger {
+    /// The `CharacterMeta` is moving.
+    Moving,
+    /// The `CharacterMeta` is not moving.
+    Standing,
+    /// The character starts an attack.
+    Attacking,
+    /// The character was hit and is knocked back.
+    Hurt,
+    /// The health of the character is used up.
+    Dead,
+    /// The clip of the current state played once to its end.
+    Finished,
+}
+
+/// Clip of a state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateClip {
+    /// Name of the animations, `{name}` is replaced by the character and
+    /// `{direction}` by up, down, left or right.
+    pub clip: String,
+    /// Only show this frame of the clip, like the standing frame of a walk.
+    #[serde(default)]
+    pub frame: Option<usize>,
+    /// Playback mode instead of the one of the clip.
+    #[serde(default)]
+    pub mode: Option<PlaybackMode>,
+}
+
+impl StateClip {
+    pub fn new(clip: &str) -> Self {
+        StateClip {
+            clip: clip.to_string(),
+            frame: None,
+            mode: None,
+        }
+    }
+
+    /// Name of the animation for a character and direction.
+    pub fn clip_name(&self, name: &str, direction: CharacterDirection) -> String {
+        self.clip
+            .replace("{name}", name)
+            .replace("{direction}", direction.as_str())
+    }
+
+    /// Animation of the clip or `None` if it's not in the atlases.
+    fn resolve(
+        &self,
+        store: &SpriteAnimationStore,
+        name: &str,
+        direction: CharacterDirection,
+    ) -> Option<AnimationDefinition> {
+        let (_, animation) = store.get_animation(&self.clip_name(name, direction))?;
+        let mut animation = match self.frame {
+            Some(frame) => {
+                let frame = frame.min(animation.keys.len().saturating_sub(1));
+                AnimationDefinition {
+                    keys: animation.keys.get(frame).copied().into_iter().collect(),
+                    durations: animation.durations.get(frame).copied().into_iter().collect(),
+                    ..AnimationDefinition::default()
+                }
+            }
+            None => animation.clone(),
+        };
+        if let Some(mode) = self.mode {
+            animation.mode = mode;
+        }
+        Some(animation)
+    }
+}
+
+/// Switch to the state `to` if `when` happens in one of the `from` states.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationTransition {
+    /// States in which the transition applies, all states if empty.
+    #[serde(default)]
+    pub from: Vec<AnimationState>,
+    pub to: AnimationState,
+    pub when: AnimationTrigger,
+}
+
+/// Animation states of a kind of character.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CharacterAnimationType {
+    pub initial_state: AnimationState,
+    pub states: BTreeMap<AnimationState, StateClip>,
+    /// Checked in order, the first matching transition is taken.
+    pub transitions: Vec<AnimationTransition>,
+}
+
+impl Default for CharacterAnimationType {
+    fn default() -> Self {
+        let mut states = BTreeMap::new();
+        states.insert(
+            AnimationState::Idle,
+            StateClip {
+                frame: Some(0),
+                ..StateClip::new("{name}_walk_{direction}")
+            },
+        );
+        states.insert(AnimationState::Walk, StateClip::new("{name}_walk_{direction}"));
+        CharacterAnimationType {
+            initial_state: AnimationState::Idle,
+            states,
+            transitions: vec![
+                AnimationTransition {
+                    from: vec![AnimationState::Idle],
+                    to: AnimationState::Walk,
+                    when: AnimationTrigger::Moving,
+                },
+                AnimationTransition {
+                    from: vec![AnimationState::Walk],
+                    to: AnimationState::Idle,
+                    when: AnimationTrigger::Standing,
+                },
+            ],
+        }
+    }
+}
+
+/// RON file definition.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CharacterAnimationData {
+    /// Used for characters which are not listed.
+    pub default: CharacterAnimationType,
+    pub characters: BTreeMap<String, CharacterAnimationType>,
+}
+
+/// Resource with the animation states of all characters.
+#[derive(Clone, Debug, Default)]
+pub struct CharacterAnimationLibrary {
+    pub characters: BTreeMap<String, CharacterAnimationType>,
+    /// Used if a character is not listed.
+    pub fallback: CharacterAnimationType,
+}
+
+impl CharacterAnimationLibrary {
+    pub fn new(data: CharacterAnimationData) -> Self {
+        CharacterAnimationLibrary {
+            characters: data.characters,
+            fallback: data.default,
+        }
+    }
+
+    /// Animation states of the character with the given name or the fallback.
+    pub fn get(&self, name: &str) -> &CharacterAnimationType {
+        self.characters.get(name).unwrap_or(&self.fallback)
+    }
+}
+
+/// Read the character animations from a RON file.
+///
+/// Errors are logged and only walking and standing are available then.
+pub fn load_character_animations(path: impl AsRef<Path>) -> CharacterAnimationLibrary {
+    let path = path.as_ref();
+    match CharacterAnimationData::load(path) {
+        Ok(data) => CharacterAnimationLibrary::new(data),
+        Err(error) => {
+            warn!("Couldn't load character animations from {}: {}", path.display(), error);
+            CharacterAnimationLibrary::default()
+        }
+    }
+}
+
+/// Animations of one state in every direction.
+#[derive(Clone, Debug)]
+pub struct DirectionalClips {
+    pub up: AnimationDefinition,
+    pub down: AnimationDefinition,
+    pub left: AnimationDefinition,
+    pub right: AnimationDefinition,
+}
+
+impl DirectionalClips {
+    pub fn get(&self, direction: CharacterDirection) -> &AnimationDefinition {
+        match direction {
+            CharacterDirection::Up => &self.up,
+            CharacterDirection::Down => &self.down,
+            CharacterDirection::Left => &self.left,
+            CharacterDirection::Right => &self.right,
+        }
+    }
+}
+
+/// Component which contains the animation state machine of a character.
 pub struct CharacterAnimation {
-    pub prev_character_meta: crate::charactermeta::CharacterMeta,
-    pub walk_up_animation: Vec<usize>,
-    pub walk_down_animation: Vec<usize>,
-    pub walk_left_animation: Vec<usize>,
-    pub walk_right_animation: Vec<usize>,
+    pub state: AnimationState,
+    pub direction: CharacterDirection,
+    /// Clips of the states which are available for the character.
+    pub clips: BTreeMap<AnimationState, DirectionalClips>,
+    pub transitions: Vec<AnimationTransition>,
+    /// If the character was knocked back in the last frame.
+    pub hurt: bool,
+}
+
+impl CharacterAnimation {
+    /// Look up the clips of all states for the character `name`.
+    ///
+    /// States without a clip for every direction are left out.
+    pub fn new(
+        name: &str,
+        animation_type: &CharacterAnimationType,
+        store: &SpriteAnimationStore,
+        direction: CharacterDirection,
+    ) -> Self {
+        let clips = animation_type
+            .states
+            .iter()
+            .filter_map(|(state, clip)| {
+                let clips = DirectionalClips {
+                    up: clip.resolve(store, name, CharacterDirection::Up)?,
+                    down: clip.resolve(store, name, CharacterDirection::Down)?,
+                    left: clip.resolve(store, name, CharacterDirection::Left)?,
+                    right: clip.resolve(store, name, CharacterDirection::Right)?,
+                };
+                Some((*state, clips))
+            })
+            .collect();
+        CharacterAnimation {
+            state: animation_type.initial_state,
+            direction,
+            clips,
+            transitions: animation_type.transitions.clone(),
+            hurt: false,
+        }
+    }
+
+    /// Clip of the current state and direction.
+    pub fn current_clip(&self) -> Option<&AnimationDefinition> {
+        self.clips
+            .get(&self.state)
+            .map(|clips| clips.get(self.direction))
+    }
+
+    /// If the character plays a die clip before it is removed.
+    pub fn can_die(&self) -> bool {
+        self.clips.contains_key(&AnimationState::Die)
+            && self
+                .transitions
+                .iter()
+                .any(|transition| transition.to == AnimationState::Die)
+    }
+
+    /// State of the first transition which applies.
+    pub fn next_state(&self, triggered: impl Fn(AnimationTrigger) -> bool) -> Option<AnimationState> {
+        self.transitions
+            .iter()
+            .find(|transition| {
+                (transition.from.is_empty() || transition.from.contains(&self.state))
+                    && transition.to != self.state
+                    && self.clips.contains_key(&transition.to)
+                    && triggered(transition.when)
+            })
+            .map(|transition| transition.to)
+    }
 }
 
 impl Component for CharacterAnimation {
     type Storage = DenseVecStorage<Self>;
 }
 
-/// System to set the animations based on the CharacterMeta
-pub struct CharacterAnimationSystem;
+/// System to switch the animation states and play their clips.
+#[derive(Default)]
+pub struct CharacterAnimationSystem {
+    reader: Option<ReaderId<AnimationFinishedEvent>>,
+}
+
 impl<'s> System<'s> for CharacterAnimationSystem {
     type SystemData = (
+        Entities<'s>,
+        Write<'s, EventChannel<AnimationFinishedEvent>>,
         WriteStorage<'s, CharacterAnimation>,
         ReadStorage<'s, CharacterMeta>,
+        ReadStorage<'s, Knockback>,
+        ReadStorage<'s, Health>,
         WriteStorage<'s, SpriteAnimation>,
     );
 
     fn run(
         &mut self,
-        (mut character_animations, character_metas, mut sprite_animations): Self::SystemData,
+        (
+            entities,
+            mut channel,
+            mut character_animations,
+            character_metas,
+            knockbacks,
+            healths,
+            mut sprite_animations,
+        ): Self::SystemData,
     ) {
-        for (mut character_animation, character_meta, mut sprite_animation) in (
+        if let None = self.reader {
+            self.reader = Some(channel.register_reader());
+        }
+        let reader = self.reader.as_mut().unwrap();
+        let finished: Vec<_> = channel.read(reader).map(|event| event.entity).collect();
+
+        for (entity, character_animation, character_meta, sprite_animation) in (
+            &entities,
             &mut character_animations,
             &character_metas,
             &mut sprite_animations,
         )
             .join()
         {
-            if character_animation.prev_character_meta != *character_meta {
-                character_animation.prev_character_meta = character_meta.clone();
-                let new_animation = match character_meta.direction {
-                    CharacterDirection::Up => character_animation.walk_up_animation.clone(),
-                    CharacterDirection::Down => character_animation.walk_down_animation.clone(),
-                    CharacterDirection::Left => character_animation.walk_left_animation.clone(),
-                    CharacterDirection::Right => character_animation.walk_right_animation.clone(),
-                };
-                sprite_animation.index = 0;
-                sprite_animation.keys = new_animation;
-                sprite_animation.pause = !character_meta.moving;
+            let knocked_back = knockbacks.contains(entity);
+            let hurt = knocked_back && !character_animation.hurt;
+            character_animation.hurt = knocked_back;
+            let dead = healths.get(entity).map_or(false, Health::is_dead);
+
+            let state = character_animation
+                .next_state(|trigger| match trigger {
+                    AnimationTrigger::Moving => character_meta.moving,
+                    AnimationTrigger::Standing => !character_meta.moving,
+                    AnimationTrigger::Attacking => character_meta.attacking,
+                    AnimationTrigger::Hurt => hurt,
+                    AnimationTrigger::Dead => dead,
+                    AnimationTrigger::Finished => finished.contains(&entity),
+                })
+                .unwrap_or(character_animation.state);
+            let state_changed = state != character_animation.state;
+            if state_changed || character_meta.direction != character_animation.direction {
+                character_animation.state = state;
+                character_animation.direction = character_meta.direction;
+                if let Some(clip) = character_animation.current_clip() {
+                    if state_changed {
+                        sprite_animation.play(clip);
+                    } else {
+                        // Only the direction changed, keep the progress.
+                        sprite_animation.switch(clip);
+                    }
+                    sprite_animation.pause = false;
+                }
             }
         }
     }
@@ -1,7 +1,7 @@
 //! Module contains the meta informatormation for characters
 //!
-//! Meta information is the direction the character looks at
-//! and if the character is moving.
+//! Meta information is the direction the character looks at,
+//! if the character is moving and if it starts an attack.
 
 use amethyst::ecs::{Component, DenseVecStorage};
 
@@ -30,6 +30,8 @@ impl CharacterDirection {
 pub struct CharacterMeta {
     pub direction: CharacterDirection,
     pub moving: bool,
+    /// Set in the frame in which the character starts an attack.
+    pub attacking: bool,
 }
 
 impl CharacterMeta {
@@ -38,6 +40,7 @@ impl CharacterMeta {
         CharacterMeta {
             direction,
             moving: false,
+            attacking: false,
         }
     }
 }
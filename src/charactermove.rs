@@ -3,12 +3,13 @@
 use amethyst::core::Transform;
 use amethyst::ecs::{Component, DenseVecStorage, LazyUpdate};
 use amethyst::ecs::{Join, Read, ReadStorage, System, WriteStorage, ReadExpect};
-use amethyst::input::{InputHandler, StringBindings};
 use specs_physics::PhysicsBody;
 use specs_physics::nphysics::algebra::Velocity3;
 
 use crate::charactermeta::{CharacterDirection, CharacterMeta};
-use crate::swordattack::sword_attack;
+use crate::health::{Health, Knockback};
+use crate::playerinput::PlayerInput;
+use crate::swordattack::{sword_attack, sword_swing};
 use crate::spriteanimationloader::SpriteAnimationStore;
 
 /// Ability to let the character move.
@@ -34,6 +35,8 @@ impl Component for UserMove {
 }
 
 /// System to handle user input and set the speed.
+///
+/// The input is read from the `PlayerInput` resource.
 pub struct CharacterMoveSystem {
     attack_released: bool
 }
@@ -52,7 +55,9 @@ impl<'s> System<'s> for CharacterMoveSystem {
         ReadStorage<'s, CharacterMove>,
         ReadStorage<'s, UserMove>,
         ReadStorage<'s, Transform>,
-        Read<'s, InputHandler<StringBindings>>,
+        ReadStorage<'s, Knockback>,
+        ReadStorage<'s, Health>,
+        Read<'s, PlayerInput>,
         Read<'s, LazyUpdate>,
         ReadExpect<'s, SpriteAnimationStore>,
     );
@@ -65,42 +70,53 @@ impl<'s> System<'s> for CharacterMoveSystem {
             character_moves,
             user_moves,
             transforms,
+            knockbacks,
+            healths,
             input,
             lazy_update,
             sprite_animation_store,
         ): Self::SystemData,
     ) {
-        for (character_meta, physics_body, character_move, _, transform) in (
+        for (character_meta, physics_body, character_move, _, transform, knockback, health) in (
             &mut character_meta,
             &mut physics_body,
             &character_moves,
             &user_moves,
             &transforms,
+            knockbacks.maybe(),
+            healths.maybe(),
         )
             .join()
         {
+            // The dead player doesn't react anymore.
+            let input = if health.map_or(false, Health::is_dead) {
+                PlayerInput::default()
+            } else {
+                *input
+            };
             let mut velocity_x = 0.0;
             let mut velocity_y = 0.0;
             let mut movement = false;
-            if input.axis_value("player_move_x").unwrap() > 0.0 {
+            character_meta.attacking = false;
+            if input.move_x > 0.0 {
                 character_meta.direction = CharacterDirection::Right;
                 character_meta.moving = true;
                 velocity_x += character_move.speed;
                 movement = true;
             }
-            if input.axis_value("player_move_x").unwrap() < 0.0 {
+            if input.move_x < 0.0 {
                 character_meta.direction = CharacterDirection::Left;
                 character_meta.moving = true;
                 velocity_x -= character_move.speed;
                 movement = true;
             }
-            if input.axis_value("player_move_y").unwrap() > 0.0 {
+            if input.move_y > 0.0 {
                 character_meta.direction = CharacterDirection::Up;
                 character_meta.moving = true;
                 velocity_y += character_move.speed;
                 movement = true;
             }
-            if input.axis_value("player_move_y").unwrap() < 0.0 {
+            if input.move_y < 0.0 {
                 character_meta.direction = CharacterDirection::Down;
                 character_meta.moving = true;
                 velocity_y -= character_move.speed;
@@ -109,16 +125,22 @@ impl<'s> System<'s> for CharacterMoveSystem {
             if !movement {
                 character_meta.moving = false;
             }
-            physics_body.velocity = Velocity3::linear(velocity_x, velocity_y, 0.0);
-            if input.action_is_down("attack").unwrap() {
+            if let Some(knockback) = knockback {
+                physics_body.velocity =
+                    Velocity3::linear(knockback.velocity.0, knockback.velocity.1, 0.0);
+            } else {
+                physics_body.velocity = Velocity3::linear(velocity_x, velocity_y, 0.0);
+            }
+            if input.attack {
                 if self.attack_released {
                     self.attack_released = false;
+                    character_meta.attacking = true;
                     let transform: Transform = transform.clone();
                     let direction: CharacterDirection = character_meta.direction.clone();
-                    let sprite_name = format!("sword-attack-{}", direction.as_str());
-                    let sprite = sprite_animation_store.get_sprite_render(&sprite_name).unwrap();
+                    let (sprite, animation) = sword_swing(&sprite_animation_store, &direction)
+                        .unwrap_or_else(|| panic!("Missing sword sprite for {}", direction.as_str()));
                     lazy_update.exec_mut(move |world| {
-                        sword_attack(world, 1.0, transform, direction, sprite);
+                        sword_attack(world, 1.0, transform, direction, sprite, animation);
                     });
                 }
             } else {
@@ -0,0 +1,118 @@
+//! Game configuration
+//!
+//! The configuration is loaded from `resources/game_config.ron` and
+//! can be overridden by command line arguments.
+
+use crate::camera::CameraConfig;
+use crate::geometry::WorldGeometry;
+use crate::layout::LayoutConfig;
+use serde::{Deserialize, Serialize};
+
+/// Settings which are read on startup.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// Seed for the dungeon generation.
+    ///
+    /// If no seed is set, a random one is chosen on startup.
+    pub seed: Option<u64>,
+
+    /// Algorithm which places the rooms of the dungeon.
+    pub layout: LayoutConfig,
+
+    /// Size of tiles and generated rooms.
+    pub geometry: WorldGeometry,
+
+    /// Visible area and how the camera follows the player.
+    pub camera: CameraConfig,
+
+    /// RON file with the room prefabs.
+    pub prefab_file: Option<String>,
+
+    /// Load the dungeon from this file instead of generating it.
+    pub map_file: Option<String>,
+
+    /// Store the dungeon in this file after it was generated.
+    pub save_map: Option<String>,
+
+    /// File used by quick save and quick load.
+    ///
+    /// Defaults to `savegame.ron`, relative to the application root.
+    pub save_file: Option<String>,
+
+    /// Record the input and store it as replay in this file when the game ends.
+    pub record_replay: Option<String>,
+
+    /// Play the replay from this file instead of reading the keyboard.
+    ///
+    /// The dungeon and seed of the replay are used instead of the configured ones.
+    pub replay: Option<String>,
+}
+
+impl GameConfig {
+    /// Override the settings with the given command line arguments.
+    ///
+    /// Supported arguments:
+    /// * `--seed <number>`
+    /// * `--layout <random-walk|grid|bsp|linear>`
+    /// * `--prefabs <file>`
+    /// * `--map <file>`
+    /// * `--save-map <file>`
+    /// * `--save-file <file>`
+    /// * `--record <file>`
+    /// * `--replay <file>`
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => match args.next().map(|seed| seed.parse::<u64>()) {
+                    Some(Ok(seed)) => self.seed = Some(seed),
+                    Some(Err(error)) => warn!("Invalid seed: {}", error),
+                    None => warn!("Missing value for --seed"),
+                },
+                "--layout" => match args.next() {
+                    Some(name) => match LayoutConfig::from_name(&name) {
+                        Some(layout) => self.layout = layout,
+                        None => warn!("Unknown layout: {}", name),
+                    },
+                    None => warn!("Missing value for --layout"),
+                },
+                "--prefabs" => match args.next() {
+                    Some(path) => self.prefab_file = Some(path),
+                    None => warn!("Missing value for --prefabs"),
+                },
+                "--map" => match args.next() {
+                    Some(path) => self.map_file = Some(path),
+                    None => warn!("Missing value for --map"),
+                },
+                "--save-map" => match args.next() {
+                    Some(path) => self.save_map = Some(path),
+                    None => warn!("Missing value for --save-map"),
+                },
+                "--save-file" => match args.next() {
+                    Some(path) => self.save_file = Some(path),
+                    None => warn!("Missing value for --save-file"),
+                },
+                "--record" => match args.next() {
+                    Some(path) => self.record_replay = Some(path),
+                    None => warn!("Missing value for --record"),
+                },
+                "--replay" => match args.next() {
+                    Some(path) => self.replay = Some(path),
+                    None => warn!("Missing value for --replay"),
+                },
+                _ => warn!("Unknown argument: {}", arg),
+            }
+        }
+    }
+
+    /// File used by quick save and quick load.
+    pub fn save_file(&self) -> &str {
+        self.save_file.as_ref().map_or("savegame.ron", |path| path.as_str())
+    }
+
+    /// Return the configured seed or choose a random one.
+    pub fn seed_or_random(&self) -> u64 {
+        self.seed.unwrap_or_else(rand::random)
+    }
+}
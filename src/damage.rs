@@ -1,11 +1,14 @@
 //! Handle damages
 
 use amethyst::{
-    ecs::{ReadStorage, System, WriteStorage, Component, DenseVecStorage, Entities, Write},
+    ecs::{Entity, ReadStorage, System, WriteStorage, Component, DenseVecStorage, Entities, Write},
 };
 use specs_physics::events::{ProximityEvent, ProximityEvents};
 use amethyst::core::shrev::{ReaderId};
 
+use crate::pathfinding::NavigationGrid;
+use crate::roomstate::RoomTile;
+
 
 
 
@@ -42,9 +45,14 @@ impl<'s> System<'s> for DestroySystem {
         WriteStorage<'s, Destroyable>,
         Entities<'s>,
         Write<'s, ProximityEvents>,
+        ReadStorage<'s, RoomTile>,
+        Write<'s, NavigationGrid>,
     );
 
-    fn run(&mut self, (destroyers, mut destroyables, entities, mut channel): Self::SystemData) {         
+    fn run(
+        &mut self,
+        (destroyers, mut destroyables, entities, mut channel, room_tiles, mut navigation_grid): Self::SystemData,
+    ) {
         if let None = self.reader {
             self.reader = Some(channel.register_reader());
         }
@@ -56,10 +64,7 @@ impl<'s> System<'s> for DestroySystem {
                     let collider = collision.collider1.clone();
                     destroyable.health -= destroyer.damage;
                     if destroyable.health < 0.0 {
-                        if let Err(error) = entities.delete(collider) {
-                            warn!("Couldn't remove entity {} with zero health: {}",
-                                collider.id(), error);
-                        }
+                        destroy(&entities, &room_tiles, &mut navigation_grid, collider);
                     }
                 }
                 if let (Some(destroyable), Some(destroyer)) = (destroyables.get_mut(collision.collider2), destroyers.get(collision.collider1)) {
@@ -67,13 +72,26 @@ impl<'s> System<'s> for DestroySystem {
                     let collider = collision.collider2.clone();
                     destroyable.health -= destroyer.damage;
                     if destroyable.health < 0.0 {
-                        if let Err(error) = entities.delete(collider) {
-                            warn!("Couldn't remove entity {} with zero health: {}",
-                                collider.id(), error);
-                        }
+                        destroy(&entities, &room_tiles, &mut navigation_grid, collider);
                     }
                 }
             }
         }
     }
 }
+
+/// Remove a destroyed entity, its tile can be walked on afterwards.
+fn destroy(
+    entities: &Entities,
+    room_tiles: &ReadStorage<RoomTile>,
+    navigation_grid: &mut NavigationGrid,
+    entity: Entity,
+) {
+    if let Some(RoomTile(x, y)) = room_tiles.get(entity) {
+        navigation_grid.set_passable((*x, *y), true);
+    }
+    if let Err(error) = entities.delete(entity) {
+        warn!("Couldn't remove entity {} with zero health: {}",
+            entity.id(), error);
+    }
+}
@@ -0,0 +1,393 @@
+//! Game logic which is shared by the window and the headless mode.
+//!
+//! `GameSession` holds the progress of the run outside of the ECS world.
+//! The world only contains the entities of the current room, they are
+//! created by `populate_room` whenever the player enters a room.
+
+use std::path::Path;
+
+use amethyst::{
+    core::transform::Transform,
+    ecs::{DispatcherBuilder, Join, ReadStorage},
+    prelude::*,
+    renderer::SpriteRender,
+};
+use specs_physics::systems::*;
+
+use crate::behavior::{self, EnemyLibrary};
+use crate::characteranimation::CharacterAnimationLibrary;
+use crate::camera::RoomBounds;
+use crate::geometry::WorldGeometry;
+use crate::map::{self, Coordinate};
+use crate::pathfinding::NavigationGrid;
+use crate::room::{Room, RoomField, RoomRole};
+use crate::roomexit::PerformRoomExit;
+use crate::roomstate::{self, RoomState, RoomTile};
+use crate::savegame::SaveGame;
+use crate::spriteanimationloader::{self, SpriteAnimationStore};
+use crate::gamerng::{EffectRng, GameRng};
+use crate::health::{Friendly, Health, HealthSystem, PLAYER_HEALTH};
+use crate::{
+    characteranimation, charactermove, damage, delayedremove, forces, helper, roomexit,
+    spriteanimation, swordattack,
+};
+
+/// Duration of a frame in seconds if the game runs with a fixed timestep.
+///
+/// Used by the headless mode and while recording replays.
+pub const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// Progress of the current run.
+pub struct GameSession {
+    /// Seed of the dungeon, also used for the `GameRng` and the `EffectRng`.
+    pub seed: u64,
+    pub geometry: WorldGeometry,
+    pub map: map::Map<Room>,
+    pub room_states: map::Map<RoomState>,
+    pub room_coordinate: Coordinate,
+    /// Pixel position where the player appears instead of the player field.
+    pub spawn_player: Option<(f32, f32)>,
+    /// Health of the player, it is kept when the player changes the room.
+    pub player_health: f32,
+}
+
+impl GameSession {
+    /// Start a new run in the start room of the map.
+    pub fn new(map: map::Map<Room>, seed: u64, geometry: WorldGeometry) -> Self {
+        GameSession {
+            seed,
+            geometry,
+            room_coordinate: map.start_room(),
+            map,
+            room_states: map::Map::new(),
+            spawn_player: None,
+            player_health: PLAYER_HEALTH,
+        }
+    }
+
+    /// Remember the changes in the current room before leaving it.
+    pub fn store_room_state(&mut self, world: &mut World) {
+        if let Some(room) = self.map.get_room(self.room_coordinate) {
+            let previous = self
+                .room_states
+                .get_room(self.room_coordinate)
+                .cloned()
+                .unwrap_or_default();
+            let state = roomstate::capture_room_state(world, room, &previous);
+            self.room_states.add_room(self.room_coordinate, state);
+        }
+    }
+
+    /// Move to the next room if the `RoomExitSystem` requested it.
+    ///
+    /// Returns true if the room changed.  The caller has to clear the
+    /// world and populate the new room afterwards.
+    pub fn handle_room_exit(&mut self, world: &mut World) -> bool {
+        let room_exit = world.fetch_mut::<Option<PerformRoomExit>>().take();
+        if let Some(PerformRoomExit(dest_room)) = room_exit {
+            if let Some(spawn_coordinates) = self.map.spawn_point(self.room_coordinate, dest_room) {
+                self.store_room_state(world);
+                if let Some(health) = player_health(world) {
+                    self.player_health = health;
+                }
+                self.room_coordinate = dest_room.to_absolute_coordinates(self.room_coordinate);
+                info!("New coordinate: {:?}", self.room_coordinate);
+                self.spawn_player = Some(self.geometry.tile_to_pixel(spawn_coordinates));
+                return true;
+            }
+            warn!("Exit in room {:?} leads nowhere", self.room_coordinate);
+        }
+        false
+    }
+
+    /// Start again in the start room after the player died.
+    ///
+    /// The changes in the rooms are kept.  The caller has to clear the
+    /// world and populate the room afterwards.
+    pub fn respawn(&mut self, world: &mut World) {
+        self.store_room_state(world);
+        // The player died before reaching the exit.
+        world.fetch_mut::<Option<PerformRoomExit>>().take();
+        self.room_coordinate = self.map.start_room();
+        self.spawn_player = None;
+        self.player_health = PLAYER_HEALTH;
+        info!("Respawn in room {:?}", self.room_coordinate);
+    }
+
+    /// Collect the progress including the changes in the current room.
+    pub fn save_game(&mut self, world: &mut World) -> SaveGame {
+        self.store_room_state(world);
+        if let Some(health) = player_health(world) {
+            self.player_health = health;
+        }
+        SaveGame {
+            map: self.map.clone(),
+            room_states: self.room_states.clone(),
+            room_coordinate: self.room_coordinate,
+            player_position: player_position(world),
+            player_health: self.player_health,
+        }
+    }
+
+    /// Continue with a saved run.
+    ///
+    /// The caller has to clear the world and populate the room afterwards.
+    pub fn load_game(&mut self, save_game: SaveGame) {
+        self.map = save_game.map;
+        self.room_states = save_game.room_states;
+        self.room_coordinate = save_game.room_coordinate;
+        self.spawn_player = save_game.player_position;
+        self.player_health = save_game.player_health;
+    }
+}
+
+/// Pixel position of the player if there is one.
+pub fn player_position(world: &World) -> Option<(f32, f32)> {
+    let (user_moves, transforms): (ReadStorage<charactermove::UserMove>, ReadStorage<Transform>) =
+        world.system_data();
+    (&user_moves, &transforms)
+        .join()
+        .next()
+        .map(|(_, transform)| (transform.translation().x, transform.translation().y))
+}
+
+/// Remaining health of the player if there is one.
+pub fn player_health(world: &World) -> Option<f32> {
+    let (user_moves, healths): (ReadStorage<charactermove::UserMove>, ReadStorage<Health>) =
+        world.system_data();
+    (&user_moves, &healths)
+        .join()
+        .next()
+        .map(|(_, health)| health.health)
+}
+
+/// Register components which are only used to create entities and add
+/// the resources of the session.
+///
+/// Everything else is registered by the systems during the dispatcher
+/// setup.  The enemy types and character animations are read from
+/// `resources/enemies.ron` and `resources/character_animations.ron` below
+/// `app_root`.
+pub fn setup_world(session: &GameSession, world: &mut World, app_root: &Path) {
+    world.register::<RoomTile>();
+    world.register::<SpriteRender>();
+    world.insert(GameRng::from_seed(session.seed));
+    world.insert(EffectRng::from_seed(session.seed));
+    world.insert(session.geometry);
+    world.insert(behavior::load_enemies(app_root.join("resources/enemies.ron")));
+    world.insert(characteranimation::load_character_animations(
+        app_root.join("resources/character_animations.ron"),
+    ));
+}
+
+/// Add all systems which are required for the gameplay.
+///
+/// Input handling and rendering are not part of it, so the systems can
+/// also run in the headless mode.  The dispatcher must already contain a
+/// system called `player_input` which writes the `PlayerInput`, see
+/// `playerinput`.
+pub fn add_game_systems<'a, 'b>(
+    dispatcher_builder: DispatcherBuilder<'a, 'b>,
+    world: &mut World,
+) -> DispatcherBuilder<'a, 'b> {
+    dispatcher_builder
+        .with(delayedremove::DelayedRemoveSystem, "delayed_remove", &[])
+        .with(
+            spriteanimation::SpriteAnimationSystem,
+            "sprite_animation",
+            &[],
+        )
+        .with(swordattack::SwordHitSystem::default(), "sword_hit", &["sprite_animation"])
+        .with(forces::ForceSystem, "force_system", &[])
+        .with(behavior::BehaviorSystem, "behavior", &[])
+        .with(charactermove::CharacterMoveSystem::default(), "character_move", &["player_input"])
+        .with(
+            characteranimation::CharacterAnimationSystem::default(),
+            "character_animation",
+            &["sprite_animation", "character_move"],
+        )
+        .with(SyncBodiesToPhysicsSystem::<f32, Transform>::default(),
+            "sync_bodies_to_physics_system",
+            &["character_move", "behavior", "delayed_remove", "sword_hit"],
+        )
+        .with(SyncCollidersToPhysicsSystem::<f32, Transform>::default(),
+            "sync_colliders_to_physics_system",
+            &["sync_bodies_to_physics_system"],
+        )
+        .with(SyncParametersToPhysicsSystem::<f32>::default(),
+            "sync_gravity_to_physics_system",
+            &[],
+        )
+        .with(PhysicsStepperSystem::<f32>::default(),
+            "physics_stepper_system",
+            &[
+                "sync_bodies_to_physics_system",
+                "sync_colliders_to_physics_system",
+                "sync_gravity_to_physics_system",
+            ],
+        )
+        .with(SyncBodiesFromPhysicsSystem::<f32, Transform>::default(),
+            "sync_bodies_from_physics_system",
+            &["physics_stepper_system"],
+        )
+        .with(roomexit::RoomExitSystem::new(world), "roomexit", &["sync_bodies_from_physics_system"])
+        .with(damage::DestroySystem::default(), "destroy", &["sync_bodies_from_physics_system"])
+        .with(HealthSystem::default(), "health", &["sync_bodies_from_physics_system"])
+}
+
+/// Health of a bush, the bushes which hide a treasure are tougher.
+fn bush_health(role: RoomRole) -> f32 {
+    match role {
+        RoomRole::Treasure => 4.0,
+        _ => 2.0,
+    }
+}
+
+/// Create the entities of the current room.
+///
+/// The role of the room decides what is spawned: start rooms have no
+/// enemies, the first enemy of a boss room is a boss type and the others
+/// are normal enemies, and treasure rooms have tougher bushes.
+pub fn populate_room(session: &GameSession, world: &mut World) {
+    if !world.has_value::<SpriteAnimationStore>() {
+        info!("Loading sprites");
+        let sprite_animations = spriteanimationloader::load_sprite_store(world, "texture", "atlases.ron")
+            .unwrap_or_else(|error| panic!("{}", error));
+        world.insert(sprite_animations);
+    }
+    let sprite_animations = world.read_resource::<SpriteAnimationStore>().clone();
+
+    info!("Getting room: {:?}", session.room_coordinate);
+    let room = session.map.get_room(session.room_coordinate).unwrap();
+    info!("Entering {:?} room", room.role);
+    let room_state = session
+        .room_states
+        .get_room(session.room_coordinate)
+        .cloned()
+        .unwrap_or_default();
+    let enemy_library = world.read_resource::<EnemyLibrary>().clone();
+    let character_animations = world.read_resource::<CharacterAnimationLibrary>().clone();
+    let mut navigation_grid = NavigationGrid::from_room(room);
+    for tile in &room_state.removed {
+        navigation_grid.set_passable(*tile, true);
+    }
+    world.insert(navigation_grid);
+    world.insert(RoomBounds {
+        width: room.width as f32 * session.geometry.tile_size,
+        height: room.height as f32 * session.geometry.tile_size,
+    });
+
+    // The boss stays on its tile, so it isn't replaced by another enemy once it's defeated.
+    let boss_tile = room
+        .room_field_iterator()
+        .find(|(_, _, field)| *field == RoomField::Enemy)
+        .map(|(x, y, _)| (x, y));
+
+    for (x, y, field) in room.room_field_iterator() {
+        let pixel_pos = session.geometry.tile_to_pixel((x, y));
+
+        if room_state.is_removed((x, y)) {
+            continue;
+        }
+        match field {
+            RoomField::Nothing => {},
+            RoomField::Wall => {
+                // Add a brick
+                helper::create_solid(
+                    world.create_entity(),
+                    &sprite_animations,
+                    &session.geometry,
+                    pixel_pos,
+                    "brick",
+                ).build();
+            },
+            RoomField::Stone => {
+                // Add a stone
+                helper::create_solid(
+                    world.create_entity(),
+                    &sprite_animations,
+                    &session.geometry,
+                    pixel_pos,
+                    "stones",
+                ).build();
+            },
+            RoomField::Bush => {
+                // Add a bush
+                helper::create_solid(
+                    world.create_entity(),
+                    &sprite_animations,
+                    &session.geometry,
+                    pixel_pos,
+                    "bush",
+                ).with(damage::Destroyable { health: room_state.health((x, y), bush_health(room.role)) })
+                .with(RoomTile(x, y))
+                .build();
+            },
+            RoomField::Player => {
+                if let None = session.spawn_player {
+                    create_player(session, world, &sprite_animations, &character_animations, pixel_pos);
+                }
+            },
+            RoomField::Enemy => {
+                if room.role == RoomRole::Start {
+                    continue;
+                }
+                let role = if room.role == RoomRole::Boss && boss_tile != Some((x, y)) {
+                    RoomRole::Normal
+                } else {
+                    room.role
+                };
+                let (type_name, enemy_type) = enemy_library.choose(role, (x, y));
+                helper::create_enemy(
+                    world.create_entity(),
+                    &sprite_animations,
+                    &character_animations,
+                    &session.geometry,
+                    pixel_pos,
+                    type_name,
+                    enemy_type,
+                    room_state.health((x, y), enemy_type.health),
+                ).with(RoomTile(x, y))
+                .build();
+            },
+            RoomField::Exit(direction) => {
+                helper::create_walkable_solid(
+                    world.create_entity(),
+                    &session.geometry,
+                    pixel_pos,
+                )
+                .with(direction)
+                // .with(damage::Destroyer { damage: 1.0})
+                .build();
+            },
+        }
+    }
+    if let Some(pixel_pos) = session.spawn_player {
+        info!("Setting player coordinates");
+        create_player(session, world, &sprite_animations, &character_animations, pixel_pos);
+    }
+    info!("Room setup complete");
+}
+
+fn create_player(
+    session: &GameSession,
+    world: &mut World,
+    sprite_animations: &SpriteAnimationStore,
+    character_animations: &CharacterAnimationLibrary,
+    pixel_pos: (f32, f32),
+) {
+    let mut health = Health::new(PLAYER_HEALTH);
+    health.health = session.player_health;
+    helper::create_character(
+        world.create_entity(),
+        sprite_animations,
+        character_animations,
+        &session.geometry,
+        pixel_pos,
+        "healer",
+    )
+    .with(charactermove::UserMove)
+    .with(health)
+    .with(Friendly)
+    .build();
+}
@@ -0,0 +1,85 @@
+//! Random numbers which are used while the game is running.
+//!
+//! Systems must not use `thread_rng`, otherwise replays diverge.  They
+//! draw from the `GameRng` resource instead which is seeded from the
+//! dungeon seed.
+//!
+//! Effects which don't change the gameplay, like particles, use the
+//! `EffectRng` instead.  They only run with a window, so drawing from the
+//! `GameRng` would make the game play differently than in the headless
+//! mode.  The `EffectRng` is seeded from the dungeon seed as well, because
+//! effects create entities and a windowed replay has to create the same
+//! ones as the recording.
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Seeded random number generator resource.
+pub struct GameRng(pub StdRng);
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        GameRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::from_seed(rand::random())
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+/// Mixed into the dungeon seed, so effects don't draw the same numbers
+/// as the gameplay.
+const EFFECT_SEED_MASK: u64 = 0x5eed_effe_c75e_ed00;
+
+/// Seeded random number generator resource for visual effects.
+pub struct EffectRng(pub StdRng);
+
+impl EffectRng {
+    pub fn from_seed(seed: u64) -> Self {
+        EffectRng(StdRng::seed_from_u64(seed ^ EFFECT_SEED_MASK))
+    }
+}
+
+impl Default for EffectRng {
+    fn default() -> Self {
+        EffectRng::from_seed(rand::random())
+    }
+}
+
+impl RngCore for EffectRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
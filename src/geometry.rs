@@ -0,0 +1,134 @@
+//! Size of tiles, rooms and characters.
+//!
+//! `WorldGeometry` is part of the `GameConfig` and is inserted into the
+//! world as resource.  Everything which converts between tiles and pixels
+//! or sizes sprites and colliders reads it from there.
+//!
+//! Rooms smaller than `MIN_ROOM_SIZE` are rejected while loading, room
+//! generation can't decorate them.
+
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::room::MIN_ROOM_SIZE;
+
+/// Dimensions of the game world.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "GeometryData")]
+pub struct WorldGeometry {
+    /// Width and height of a tile in pixels.
+    pub tile_size: f32,
+    /// Width of a generated room in tiles.
+    pub room_width: usize,
+    /// Height of a generated room in tiles.
+    pub room_height: usize,
+    /// Half of the width and height of a character collider in pixels.
+    pub character_half_extent: f32,
+}
+
+impl Default for WorldGeometry {
+    fn default() -> Self {
+        WorldGeometry {
+            tile_size: 32.0,
+            room_width: 20,
+            room_height: 15,
+            character_half_extent: 13.0,
+        }
+    }
+}
+
+/// Room size which can't be generated.
+#[derive(Debug)]
+pub struct GeometryError {
+    pub room_width: usize,
+    pub room_height: usize,
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Rooms must be at least {}x{} tiles, got {}x{}",
+            MIN_ROOM_SIZE, MIN_ROOM_SIZE, self.room_width, self.room_height
+        )
+    }
+}
+
+impl std::error::Error for GeometryError {}
+
+/// `WorldGeometry` as it is read from a file, before the size is checked.
+#[derive(Deserialize)]
+#[serde(default)]
+struct GeometryData {
+    tile_size: f32,
+    room_width: usize,
+    room_height: usize,
+    character_half_extent: f32,
+}
+
+impl Default for GeometryData {
+    fn default() -> Self {
+        let geometry = WorldGeometry::default();
+        GeometryData {
+            tile_size: geometry.tile_size,
+            room_width: geometry.room_width,
+            room_height: geometry.room_height,
+            character_half_extent: geometry.character_half_extent,
+        }
+    }
+}
+
+impl TryFrom<GeometryData> for WorldGeometry {
+    type Error = GeometryError;
+
+    fn try_from(data: GeometryData) -> Result<Self, GeometryError> {
+        if data.room_width < MIN_ROOM_SIZE || data.room_height < MIN_ROOM_SIZE {
+            return Err(GeometryError {
+                room_width: data.room_width,
+                room_height: data.room_height,
+            });
+        }
+        Ok(WorldGeometry {
+            tile_size: data.tile_size,
+            room_width: data.room_width,
+            room_height: data.room_height,
+            character_half_extent: data.character_half_extent,
+        })
+    }
+}
+
+impl WorldGeometry {
+    /// Width of a generated room in pixels.
+    pub fn arena_width(&self) -> f32 {
+        self.room_width as f32 * self.tile_size
+    }
+
+    /// Height of a generated room in pixels.
+    pub fn arena_height(&self) -> f32 {
+        self.room_height as f32 * self.tile_size
+    }
+
+    /// Half of the width and height of a tile, used for colliders.
+    pub fn tile_half_extent(&self) -> f32 {
+        self.tile_size / 2.0
+    }
+
+    /// Pixel position of the center of a tile.
+    pub fn tile_to_pixel(&self, (x, y): (usize, usize)) -> (f32, f32) {
+        (
+            x as f32 * self.tile_size + self.tile_half_extent(),
+            y as f32 * self.tile_size + self.tile_half_extent(),
+        )
+    }
+
+    /// Tile which contains the pixel position.
+    ///
+    /// Positions left of or below the room belong to the border tiles.
+    pub fn pixel_to_tile(&self, (x, y): (f32, f32)) -> (usize, usize) {
+        (
+            (x.max(0.0) / self.tile_size) as usize,
+            (y.max(0.0) / self.tile_size) as usize,
+        )
+    }
+}
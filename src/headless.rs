@@ -0,0 +1,159 @@
+//! Run the game without a window.
+//!
+//! The headless game owns its own world and dispatcher with the gameplay
+//! systems from `game::add_game_systems`.  There is no renderer and no
+//! keyboard, the player is controlled by an `InputScript` instead.  Every
+//! step advances the time by a fixed timestep, so the same script always
+//! leads to the same result.  This is used to test gameplay on machines
+//! without a GPU.  The input is recorded, so every run can be turned into
+//! a `Replay`.
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::{
+        timing::Time,
+        transform::TransformBundle,
+        bundle::SystemBundle,
+        ArcThreadPool,
+    },
+    ecs::{Dispatcher, DispatcherBuilder},
+    prelude::*,
+    renderer::{SpriteSheet, Texture},
+};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::game::{self, GameSession};
+use crate::health::GameOver;
+use crate::map::Coordinate;
+use crate::playerinput::{
+    InputRecording, InputScript, RecordInputSystem, ScriptedInput, ScriptedInputSystem,
+};
+use crate::replay::Replay;
+
+/// Game world which is stepped manually.
+pub struct HeadlessGame<'a, 'b> {
+    pub world: World,
+    pub session: GameSession,
+    /// How often the player died.
+    pub deaths: usize,
+    dispatcher: Dispatcher<'a, 'b>,
+    timestep: f32,
+}
+
+impl<'a, 'b> HeadlessGame<'a, 'b> {
+    /// Set up the world and populate the current room of the session.
+    ///
+    /// `app_root` is the directory which contains the `resources` and
+    /// `texture` directories.
+    pub fn new(session: GameSession, app_root: &Path, timestep: f32) -> amethyst::Result<Self> {
+        let mut world = World::new();
+        let pool: ArcThreadPool = Arc::new(rayon::ThreadPoolBuilder::new().build()?);
+        world.insert(Loader::new(app_root, pool.clone()));
+        world.insert(pool.clone());
+        world.insert(AssetStorage::<Texture>::default());
+        world.insert(AssetStorage::<SpriteSheet>::default());
+        world.insert(Time::default());
+
+        game::setup_world(&session, &mut world, app_root);
+        let mut dispatcher_builder = DispatcherBuilder::new();
+        TransformBundle::new().build(&mut world, &mut dispatcher_builder)?;
+        dispatcher_builder.add(ScriptedInputSystem, "player_input", &[]);
+        dispatcher_builder.add(RecordInputSystem, "record_input", &["player_input"]);
+        let mut dispatcher = game::add_game_systems(dispatcher_builder, &mut world)
+            .with_pool(pool)
+            .build();
+        dispatcher.setup(&mut world);
+
+        game::populate_room(&session, &mut world);
+        Ok(HeadlessGame {
+            world,
+            session,
+            deaths: 0,
+            dispatcher,
+            timestep,
+        })
+    }
+
+    /// Set up the dungeon of the replay and start to play its input.
+    pub fn from_replay(replay: Replay, app_root: &Path) -> amethyst::Result<Self> {
+        let seed = replay.seed;
+        let timestep = replay.timestep;
+        let geometry = replay.geometry;
+        let (map, script) = replay.into_parts()?;
+        let session = GameSession::new(map, seed, geometry);
+        let mut headless_game = HeadlessGame::new(session, app_root, timestep)?;
+        headless_game.play_script(script);
+        Ok(headless_game)
+    }
+
+    /// Start to play the input script from the beginning.
+    pub fn play_script(&mut self, script: InputScript) {
+        self.world.insert(ScriptedInput::new(script));
+    }
+
+    /// Run until the current input script is finished.
+    pub fn run_script(&mut self) {
+        while !self.world.read_resource::<ScriptedInput>().is_finished() {
+            self.step();
+        }
+    }
+
+    /// Advance the game by one timestep.
+    ///
+    /// If the player dies, the game respawns the player in the start room
+    /// immediately.  Returns true if the room was set up again because
+    /// the player entered another room or respawned.
+    pub fn step(&mut self) -> bool {
+        {
+            let mut time = self.world.write_resource::<Time>();
+            time.set_delta_seconds(self.timestep);
+            time.increment_frame_number();
+        }
+        self.dispatcher.dispatch(&self.world);
+        self.world.maintain();
+
+        let game_over = self.world.fetch_mut::<Option<GameOver>>().take().is_some();
+        if game_over {
+            self.deaths += 1;
+            self.session.respawn(&mut self.world);
+        }
+        if game_over || self.session.handle_room_exit(&mut self.world) {
+            self.world.delete_all();
+            game::populate_room(&self.session, &mut self.world);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Run as many steps as fit into the given time.
+    pub fn run_for(&mut self, seconds: f32) {
+        let steps = (seconds / self.timestep).round() as usize;
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    /// Replay of the input since the game was created.
+    ///
+    /// It starts like a new session with the same map and seed, so the
+    /// session must not have been changed before the first step.
+    pub fn replay(&self) -> Replay {
+        Replay::new(
+            self.session.seed,
+            self.timestep,
+            self.session.geometry,
+            &self.session.map,
+            self.world.read_resource::<InputRecording>().frames.clone(),
+        )
+    }
+
+    pub fn room_coordinate(&self) -> Coordinate {
+        self.session.room_coordinate
+    }
+
+    pub fn player_position(&self) -> Option<(f32, f32)> {
+        game::player_position(&self.world)
+    }
+}
@@ -0,0 +1,437 @@
+//! Health of characters and damage they take.
+//!
+//! Characters with `Health` are hurt when they come close to a
+//! `Destroyer` of the other side: the player and the sword are `Friendly`,
+//! the enemies are not.  After a hit they are invulnerable for a short
+//! time and pushed away from the source.  Dead characters stop moving and
+//! play their die clip if they have one.  Afterwards `GameOver` is
+//! requested for the player and other characters are removed.
+
+use amethyst::{
+    core::{
+        shrev::{EventChannel, ReaderId},
+        timing::Time,
+        Transform,
+    },
+    ecs::{Component, DenseVecStorage, Entities, Entity, Join, NullStorage, Read, ReadStorage, System, Write, WriteStorage},
+};
+
+use crate::characteranimation::{AnimationState, CharacterAnimation};
+use crate::charactermove::UserMove;
+use crate::damage::Destroyer;
+use crate::geometry::WorldGeometry;
+use crate::spriteanimation::{AnimationFinishedEvent, SpriteAnimation};
+
+/// Health of the player when the game starts or after a respawn.
+pub const PLAYER_HEALTH: f32 = 5.0;
+
+/// Health of a character.
+pub struct Health {
+    pub health: f32,
+    pub max_health: f32,
+    /// Seconds until the character can be hurt again.
+    pub invulnerable: f32,
+}
+
+impl Health {
+    pub fn new(max_health: f32) -> Self {
+        Health {
+            health: max_health,
+            max_health,
+            invulnerable: 0.0,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.health <= 0.0
+    }
+}
+
+impl Component for Health {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Pushes a character away after a hit.
+///
+/// While it exists, the velocity of the character is overridden.
+pub struct Knockback {
+    pub velocity: (f32, f32),
+    /// Seconds until the character can move again.
+    pub remaining: f32,
+}
+
+impl Component for Knockback {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Belongs to the side of the player, like the player and its sword.
+///
+/// Destroyers only hurt characters of the other side.
+#[derive(Default)]
+pub struct Friendly;
+
+impl Component for Friendly {
+    type Storage = NullStorage<Self>;
+}
+
+/// Request to end the run because the player died.
+pub struct GameOver;
+
+/// Applies damage to characters and handles their death.
+pub struct HealthSystem {
+    /// Gap between two characters which still counts as contact.
+    pub contact_margin: f32,
+    /// Seconds a character can't be hurt after a hit.
+    pub invulnerability: f32,
+    pub knockback_speed: f32,
+    pub knockback_duration: f32,
+    reader: Option<ReaderId<AnimationFinishedEvent>>,
+}
+
+impl Default for HealthSystem {
+    fn default() -> Self {
+        HealthSystem {
+            contact_margin: 2.0,
+            invulnerability: 1.0,
+            knockback_speed: 256.0,
+            knockback_duration: 0.15,
+            reader: None,
+        }
+    }
+}
+
+impl<'s> System<'s> for HealthSystem {
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, Time>,
+        Read<'s, WorldGeometry>,
+        WriteStorage<'s, Health>,
+        WriteStorage<'s, Knockback>,
+        ReadStorage<'s, Destroyer>,
+        ReadStorage<'s, Friendly>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, UserMove>,
+        ReadStorage<'s, CharacterAnimation>,
+        ReadStorage<'s, SpriteAnimation>,
+        Write<'s, EventChannel<AnimationFinishedEvent>>,
+        Write<'s, Option<GameOver>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            time,
+            geometry,
+            mut healths,
+            mut knockbacks,
+            destroyers,
+            friendlies,
+            transforms,
+            user_moves,
+            character_animations,
+            sprite_animations,
+            mut channel,
+            mut game_over,
+        ): Self::SystemData,
+    ) {
+        if let None = self.reader {
+            self.reader = Some(channel.register_reader());
+        }
+        let reader = self.reader.as_mut().unwrap();
+        let finished: Vec<_> = channel.read(reader).map(|event| event.entity).collect();
+
+        let delta = time.delta_seconds();
+        // Sources are as large as a character.
+        let contact_distance = 2.0 * geometry.character_half_extent + self.contact_margin;
+
+        let mut finished_knockbacks = Vec::new();
+        for (entity, knockback) in (&entities, &mut knockbacks).join() {
+            knockback.remaining -= delta;
+            if knockback.remaining <= 0.0 {
+                finished_knockbacks.push(entity);
+            }
+        }
+        for entity in finished_knockbacks {
+            knockbacks.remove(entity);
+        }
+
+        // Dead characters don't hurt anymore.
+        let sources: Vec<_> = (&entities, &destroyers, &transforms, healths.maybe())
+            .join()
+            .filter(|(_, _, _, health)| !health.map_or(false, Health::is_dead))
+            .map(|(entity, destroyer, transform, _)| {
+                let translation = transform.translation();
+                let friendly = friendlies.contains(entity);
+                (entity, translation.x, translation.y, destroyer.damage, friendly)
+            })
+            .collect();
+
+        let mut died = Vec::new();
+        for (entity, health, transform) in (&entities, &mut healths, &transforms).join() {
+            if health.is_dead() {
+                // Waits for the end of the die clip.
+                let die_clip_finished = finished.contains(&entity)
+                    && character_animations
+                        .get(entity)
+                        .map_or(false, |animation| animation.state == AnimationState::Die)
+                    && sprite_animations
+                        .get(entity)
+                        .map_or(true, |sprite_animation| sprite_animation.finished);
+                if die_clip_finished {
+                    died.push(entity);
+                }
+                continue;
+            }
+            health.invulnerable = (health.invulnerable - delta).max(0.0);
+            if health.invulnerable > 0.0 {
+                continue;
+            }
+            let translation = transform.translation();
+            let friendly = friendlies.contains(entity);
+            let hit = sources.iter().find(|(source, x, y, _, source_friendly)| {
+                *source != entity
+                    && *source_friendly != friendly
+                    && (translation.x - x).hypot(translation.y - y) < contact_distance
+            });
+            if let Some((_, x, y, damage, _)) = hit {
+                health.health -= damage;
+                health.invulnerable = self.invulnerability;
+                info!("Character {} was hit, health: {}", entity.id(), health.health);
+
+                let (direction_x, direction_y) = (translation.x - x, translation.y - y);
+                let length = direction_x.hypot(direction_y);
+                let (direction_x, direction_y) = if length > 0.0 {
+                    (direction_x / length, direction_y / length)
+                } else {
+                    (0.0, 1.0)
+                };
+                let knockback = Knockback {
+                    velocity: (
+                        direction_x * self.knockback_speed,
+                        direction_y * self.knockback_speed,
+                    ),
+                    remaining: self.knockback_duration,
+                };
+                if let Err(error) = knockbacks.insert(entity, knockback) {
+                    warn!("Couldn't knock back entity {}: {}", entity.id(), error);
+                }
+
+                let can_die = character_animations
+                    .get(entity)
+                    .map_or(false, CharacterAnimation::can_die);
+                if health.is_dead() && !can_die {
+                    died.push(entity);
+                }
+            }
+        }
+
+        for entity in died {
+            remove_dead(&entities, &user_moves, &mut game_over, entity);
+        }
+    }
+}
+
+/// End the run if the player died, otherwise remove the character.
+fn remove_dead(
+    entities: &Entities,
+    user_moves: &ReadStorage<UserMove>,
+    game_over: &mut Option<GameOver>,
+    entity: Entity,
+) {
+    if user_moves.contains(entity) {
+        info!("The player died");
+        *game_over = Some(GameOver);
+    } else if let Err(error) = entities.delete(entity) {
+        warn!("Couldn't remove entity {} with zero health: {}", entity.id(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::{Builder, RunNow, World, WorldExt};
+    use std::collections::BTreeMap;
+
+    use crate::characteranimation::{AnimationTransition, AnimationTrigger, DirectionalClips};
+    use crate::charactermeta::CharacterDirection;
+    use crate::spriteanimation::PlaybackMode;
+    use crate::spriteanimationloader::AnimationDefinition;
+
+    fn setup() -> (World, HealthSystem) {
+        let mut world = World::new();
+        let mut system = HealthSystem::default();
+        System::setup(&mut system, &mut world);
+        world.write_resource::<Time>().set_delta_seconds(0.1);
+        (world, system)
+    }
+
+    fn transform((x, y): (f32, f32)) -> Transform {
+        let mut transform = Transform::default();
+        transform.set_translation_xyz(x, y, 0.0);
+        transform
+    }
+
+    fn create_player(world: &mut World, position: (f32, f32), health: f32) -> Entity {
+        world
+            .create_entity()
+            .with(UserMove)
+            .with(Friendly)
+            .with(Health { health, ..Health::new(PLAYER_HEALTH) })
+            .with(transform(position))
+            .build()
+    }
+
+    fn create_enemy(world: &mut World, position: (f32, f32), health: f32) -> Entity {
+        world
+            .create_entity()
+            .with(Destroyer { damage: 1.0 })
+            .with(Health::new(health))
+            .with(transform(position))
+            .build()
+    }
+
+    /// Animation which only has a die clip.
+    fn die_animation() -> CharacterAnimation {
+        let clip = AnimationDefinition {
+            keys: vec![0, 1],
+            mode: PlaybackMode::Once,
+            ..AnimationDefinition::default()
+        };
+        let mut clips = BTreeMap::new();
+        clips.insert(
+            AnimationState::Die,
+            DirectionalClips {
+                up: clip.clone(),
+                down: clip.clone(),
+                left: clip.clone(),
+                right: clip,
+            },
+        );
+        CharacterAnimation {
+            state: AnimationState::Idle,
+            direction: CharacterDirection::Down,
+            clips,
+            transitions: vec![AnimationTransition {
+                from: Vec::new(),
+                to: AnimationState::Die,
+                when: AnimationTrigger::Dead,
+            }],
+            hurt: false,
+        }
+    }
+
+    fn health(world: &World, entity: Entity) -> f32 {
+        world.read_storage::<Health>().get(entity).unwrap().health
+    }
+
+    #[test]
+    fn enemy_hurts_the_player_on_contact() {
+        let (mut world, mut system) = setup();
+        let player = create_player(&mut world, (100.0, 100.0), PLAYER_HEALTH);
+        let enemy = create_enemy(&mut world, (300.0, 100.0), 2.0);
+        system.run_now(&world);
+        assert_eq!(health(&world, player), PLAYER_HEALTH);
+
+        // The enemy touches the player.
+        world.write_storage::<Transform>().insert(enemy, transform((126.0, 100.0))).unwrap();
+        system.run_now(&world);
+        assert_eq!(health(&world, player), PLAYER_HEALTH - 1.0);
+        assert_eq!(health(&world, enemy), 2.0);
+    }
+
+    #[test]
+    fn player_is_invulnerable_after_a_hit() {
+        let (mut world, mut system) = setup();
+        let player = create_player(&mut world, (100.0, 100.0), PLAYER_HEALTH);
+        create_enemy(&mut world, (120.0, 100.0), 2.0);
+        system.run_now(&world);
+        system.run_now(&world);
+        assert_eq!(health(&world, player), PLAYER_HEALTH - 1.0);
+
+        world.write_resource::<Time>().set_delta_seconds(system.invulnerability);
+        system.run_now(&world);
+        assert_eq!(health(&world, player), PLAYER_HEALTH - 2.0);
+    }
+
+    #[test]
+    fn hit_knocks_back_away_from_the_source() {
+        let (mut world, mut system) = setup();
+        let player = create_player(&mut world, (100.0, 100.0), PLAYER_HEALTH);
+        create_enemy(&mut world, (100.0, 80.0), 2.0);
+        system.run_now(&world);
+        let knockbacks = world.read_storage::<Knockback>();
+        let knockback = knockbacks.get(player).unwrap();
+        assert_eq!(knockback.velocity, (0.0, system.knockback_speed));
+        assert_eq!(knockback.remaining, system.knockback_duration);
+    }
+
+    #[test]
+    fn sword_hurts_enemies_but_not_the_player() {
+        let (mut world, mut system) = setup();
+        let player = create_player(&mut world, (100.0, 100.0), PLAYER_HEALTH);
+        let enemy = create_enemy(&mut world, (150.0, 100.0), 2.0);
+        let other_enemy = create_enemy(&mut world, (150.0, 120.0), 2.0);
+        world
+            .create_entity()
+            .with(Destroyer { damage: 1.0 })
+            .with(Friendly)
+            .with(transform((132.0, 100.0)))
+            .build();
+        system.run_now(&world);
+        assert_eq!(health(&world, player), PLAYER_HEALTH);
+        assert_eq!(health(&world, enemy), 1.0);
+        assert_eq!(health(&world, other_enemy), 1.0);
+    }
+
+    #[test]
+    fn zero_health_ends_the_game_or_removes_the_enemy() {
+        let (mut world, mut system) = setup();
+        create_player(&mut world, (100.0, 100.0), 1.0);
+        let enemy = create_enemy(&mut world, (120.0, 100.0), 1.0);
+        world
+            .create_entity()
+            .with(Destroyer { damage: 1.0 })
+            .with(Friendly)
+            .with(transform((140.0, 100.0)))
+            .build();
+        system.run_now(&world);
+        world.maintain();
+        assert!(world.read_resource::<Option<GameOver>>().is_some());
+        assert!(!world.is_alive(enemy));
+    }
+
+    #[test]
+    fn dead_enemy_stays_until_its_die_clip_finished() {
+        let (mut world, mut system) = setup();
+        let player = create_player(&mut world, (100.0, 100.0), PLAYER_HEALTH);
+        let enemy = create_enemy(&mut world, (120.0, 100.0), 1.0);
+        world.write_storage::<CharacterAnimation>().insert(enemy, die_animation()).unwrap();
+        world
+            .create_entity()
+            .with(Destroyer { damage: 1.0 })
+            .with(Friendly)
+            .with(transform((140.0, 100.0)))
+            .build();
+        system.run_now(&world);
+        world.maintain();
+        assert!(world.is_alive(enemy));
+        assert_eq!(health(&world, player), PLAYER_HEALTH - 1.0);
+
+        // The dead enemy doesn't hurt the player anymore.
+        world.write_resource::<Time>().set_delta_seconds(system.invulnerability);
+        system.run_now(&world);
+        world.maintain();
+        assert!(world.is_alive(enemy));
+        assert_eq!(health(&world, player), PLAYER_HEALTH - 1.0);
+
+        // The character animation played the die clip to its end.
+        world.write_storage::<CharacterAnimation>().get_mut(enemy).unwrap().state = AnimationState::Die;
+        world
+            .write_resource::<EventChannel<AnimationFinishedEvent>>()
+            .single_write(AnimationFinishedEvent { entity: enemy });
+        system.run_now(&world);
+        world.maintain();
+        assert!(!world.is_alive(enemy));
+    }
+}
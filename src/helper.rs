@@ -1,12 +1,16 @@
 //! Contains helper functions
 
-use crate::characteranimation::CharacterAnimation;
+use crate::characteranimation::{CharacterAnimation, CharacterAnimationLibrary};
 use crate::charactermeta::CharacterDirection;
 use crate::charactermeta::CharacterMeta;
 use crate::charactermove::CharacterMove;
 use crate::spriteanimation::SpriteAnimation;
 use crate::spriteanimationloader::SpriteAnimationStore;
 use crate::forces::RadialForceField;
+use crate::damage::Destroyer;
+use crate::health::Health;
+use crate::behavior::{Behavior, EnemyType};
+use crate::geometry::WorldGeometry;
 use amethyst::{
     prelude::*,
     core::transform::Transform,
@@ -25,8 +29,9 @@ use specs_physics::{PhysicsBodyBuilder, PhysicsBody,
 /// Assigns the components to the EntityBuilder which are required
 /// to have a moving character on the screen.
 ///
-/// For the animations, it requires to have animation names following
-/// this pattern:
+/// The animation states are taken from the `CharacterAnimationLibrary`,
+/// the clip of the initial state must exist for every direction.  With
+/// the default states these are the animations
 /// * (name)_walk_up
 /// * (name)_walk_down
 /// * (name)_walk_left
@@ -39,59 +44,42 @@ use specs_physics::{PhysicsBodyBuilder, PhysicsBody,
 /// create_character(
 ///         world.create_entity(),
 ///         &animations,
+///         &character_animations,
+///         &geometry,
 ///         (300.0, 300.0),
-///         (-16.0, 16.0, -16.0, 16.0),
 ///         "hero"
 /// ).build();
 /// ```
 pub fn create_character<'a>(
     entity_builder: EntityBuilder<'a>,
     animations: &SpriteAnimationStore,
+    character_animations: &CharacterAnimationLibrary,
+    geometry: &WorldGeometry,
     (x, y): (f32, f32),
     char_name: &str,
 ) -> EntityBuilder<'a> {
     println!("Create character start");
-    let animation_up = format!("{}_walk_up", char_name);
-    let animation_down = format!("{}_walk_down", char_name);
-    let animation_left = format!("{}_walk_left", char_name);
-    let animation_right = format!("{}_walk_right", char_name);
+    let direction = CharacterDirection::Down;
+    let animation_type = character_animations.get(char_name);
+    let initial_clip = animation_type
+        .states
+        .get(&animation_type.initial_state)
+        .map(|clip| clip.clip_name(char_name, direction))
+        .unwrap_or_else(|| panic!("Missing initial animation state of {}", char_name));
+    let sprite_sheet = animations
+        .get_animation(&initial_clip)
+        .map(|(sprite_sheet, _)| sprite_sheet.clone())
+        .unwrap_or_else(|| panic!("Missing animation {}", initial_clip));
 
-    let mut sprite_animation = SpriteAnimation::new(
-        animations
-            .animations
-            .get(&animation_up)
-            .map(|x| x.clone())
-            .unwrap_or(vec![0]),
-        0.1,
-    );
-    sprite_animation.pause = true;
-    let character_meta = CharacterMeta::new(CharacterDirection::Down);
-    let character_animation = CharacterAnimation {
-        prev_character_meta: character_meta.clone(),
-        walk_up_animation: animations
-            .animations
-            .get(&animation_up)
-            .map(|x| x.clone())
-            .unwrap_or(vec![0]),
-        walk_down_animation: animations
-            .animations
-            .get(&animation_down)
-            .map(|x| x.clone())
-            .unwrap_or(vec![0]),
-        walk_left_animation: animations
-            .animations
-            .get(&animation_left)
-            .map(|x| x.clone())
-            .unwrap_or(vec![0]),
-        walk_right_animation: animations
-            .animations
-            .get(&animation_right)
-            .map(|x| x.clone())
-            .unwrap_or(vec![0]),
-    };
+    let character_meta = CharacterMeta::new(direction);
+    let character_animation = CharacterAnimation::new(char_name, animation_type, animations, direction);
+    let clip = character_animation
+        .current_clip()
+        .unwrap_or_else(|| panic!("Missing animations for the initial state of {}", char_name));
+    let sprite_animation = SpriteAnimation::from_definition(clip);
     let sprite_render = SpriteRender {
-        sprite_sheet: animations.sprite_sheet_handle.clone(),
-        sprite_number: 0,
+        sprite_sheet,
+        sprite_number: clip.keys[0],
     };
     let mut transform = Transform::default();
     transform.set_translation_xyz(x, y, -y);
@@ -101,7 +89,11 @@ pub fn create_character<'a>(
         .build();
     let physics_collider: PhysicsCollider<f32> =
         PhysicsColliderBuilder::from(Shape::Cuboid {
-            half_extents: Vector3::new(13.0, 13.0, 300.0)
+            half_extents: Vector3::new(
+                geometry.character_half_extent,
+                geometry.character_half_extent,
+                300.0,
+            )
         })
         .angular_prediction(0.0)
         .build();
@@ -124,6 +116,52 @@ pub fn create_character<'a>(
     
 }
 
+/// Assembles an enemy of the given type
+///
+/// It is a character like the player, which hurts the player on contact
+/// and is hurt by the sword.  Its movement is controlled by
+/// the `BehaviorSystem`.
+///
+/// ## Examples
+/// ```
+/// use helper::create_enemy;
+///
+/// let enemy_type = enemy_library.get("slime");
+/// create_enemy(
+///         world.create_entity(),
+///         &animations,
+///         &character_animations,
+///         &geometry,
+///         (300.0, 300.0),
+///         "slime",
+///         enemy_type,
+///         enemy_type.health
+/// ).build();
+/// ```
+pub fn create_enemy<'a>(
+    entity_builder: EntityBuilder<'a>,
+    animations: &SpriteAnimationStore,
+    character_animations: &CharacterAnimationLibrary,
+    geometry: &WorldGeometry,
+    position: (f32, f32),
+    type_name: &str,
+    enemy_type: &EnemyType,
+    health: f32,
+) -> EntityBuilder<'a> {
+    create_character(
+        entity_builder,
+        animations,
+        character_animations,
+        geometry,
+        position,
+        &enemy_type.sprite,
+    )
+        .with(CharacterMove::new(enemy_type.speed))
+        .with(Behavior::new(type_name, enemy_type.initial_state, position))
+        .with(Destroyer { damage: 1.0 })
+        .with(Health { health, ..Health::new(enemy_type.health) })
+}
+
 /// Assebles a solid entity
 ///
 /// Assigns the components to the EntityBuilder which are required
@@ -138,14 +176,15 @@ pub fn create_character<'a>(
 /// create_solid(
 ///         world.create_entity(),
 ///         &animations,
+///         &geometry,
 ///         (300.0, 300.0),
-///         (-16.0, 16.0, -16.0, 16.0),
 ///         "hero"
 /// ).build();
 /// ```
 pub fn create_solid<'a>(
     entity_builder: EntityBuilder<'a>,
     animations: &SpriteAnimationStore,
+    geometry: &WorldGeometry,
     (x, y): (f32, f32),
     name: &str,
 ) -> EntityBuilder<'a> {
@@ -156,7 +195,11 @@ pub fn create_solid<'a>(
         .build();
     let physics_collider: PhysicsCollider<f32> =
         PhysicsColliderBuilder::from(Shape::Cuboid {
-            half_extents: Vector3::new(16.0, 16.0, 300.0)
+            half_extents: Vector3::new(
+                geometry.tile_half_extent(),
+                geometry.tile_half_extent(),
+                300.0,
+            )
         })
         .build();
 
@@ -172,6 +215,7 @@ pub fn create_solid<'a>(
 
 pub fn create_walkable_solid<'a>(
     entity_builder: EntityBuilder<'a>,
+    geometry: &WorldGeometry,
     (x, y): (f32, f32),
 ) -> EntityBuilder<'a> {
     let mut transform = Transform::default();
@@ -180,7 +224,11 @@ pub fn create_walkable_solid<'a>(
         .build();
     let physics_collider: PhysicsCollider<f32> =
         PhysicsColliderBuilder::from(Shape::Cuboid {
-            half_extents: Vector3::new(16.0, 16.0, 300.0)
+            half_extents: Vector3::new(
+                geometry.tile_half_extent(),
+                geometry.tile_half_extent(),
+                300.0,
+            )
         })
         .sensor(true)
         .build();
@@ -219,10 +267,7 @@ pub fn create_walkable<'a>(
     (x, y): (f32, f32),
     name: &str,
 ) -> EntityBuilder<'a> {
-    let sprite_render = SpriteRender {
-        sprite_sheet: animations.sprite_sheet_handle.clone(),
-        sprite_number: *animations.images.get(name).unwrap_or(&0),
-    };
+    let sprite_render = animations.get_sprite_render(name).unwrap();
     let mut transform = Transform::default();
     transform.set_translation_xyz(x, y, -y);
 
@@ -0,0 +1,437 @@
+//! Algorithms which decide where the rooms of a dungeon are placed.
+//!
+//! A layout only decides which rooms exist and how they are connected.
+//! The content of the rooms is generated afterwards from the resulting
+//! `Map<RoomGeneration>`.
+
+use crate::map::{Coordinate, DungeonGen, Map};
+use crate::room::{ExitDirection, RoomGeneration};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+const DIRECTIONS: [ExitDirection; 4] = [
+    ExitDirection::North,
+    ExitDirection::South,
+    ExitDirection::East,
+    ExitDirection::West,
+];
+
+/// Creates the room layout of a dungeon.
+///
+/// Every generator must return a connected map which contains the start
+/// room at (0, 0).
+pub trait LayoutGenerator {
+    fn generate(&self, rng: &mut dyn RngCore, width: usize, height: usize) -> Map<RoomGeneration>;
+}
+
+impl LayoutGenerator for DungeonGen {
+    fn generate(&self, mut rng: &mut dyn RngCore, width: usize, height: usize) -> Map<RoomGeneration> {
+        DungeonGen::generate(self, &mut rng, width, height)
+    }
+}
+
+/// Fixed size grid of rooms.
+///
+/// The rooms are connected by a random spanning tree, afterwards
+/// `extra_loops` additional connections are added so the dungeon
+/// contains circles.
+pub struct GridLayout {
+    pub columns: u32,
+    pub rows: u32,
+    pub extra_loops: u32,
+}
+
+impl GridLayout {
+    fn contains(&self, (x, y): Coordinate) -> bool {
+        x >= 0 && y >= 0 && x < self.columns as i32 && y < self.rows as i32
+    }
+}
+
+impl LayoutGenerator for GridLayout {
+    fn generate(&self, rng: &mut dyn RngCore, width: usize, height: usize) -> Map<RoomGeneration> {
+        let mut map = Map::new();
+        let mut room = RoomGeneration::default();
+        room.width = width;
+        room.height = height;
+        map.add_room((0, 0), room);
+
+        /* Randomized depth first search for the spanning tree */
+        let mut stack = vec![(0, 0)];
+        while let Some(coordinate) = stack.last().cloned() {
+            let candidates: Vec<ExitDirection> = DIRECTIONS
+                .iter()
+                .cloned()
+                .filter(|direction| {
+                    let neighbor = direction.add(coordinate);
+                    self.contains(neighbor) && !map.has_room(neighbor)
+                })
+                .collect();
+            if candidates.is_empty() {
+                stack.pop();
+            } else {
+                let direction = candidates[rng.gen_range(0, candidates.len())];
+                stack.push(map.connect(coordinate, direction, width, height));
+            }
+        }
+
+        /* Add loops between random neighbors which are not connected yet */
+        let mut loops = 0;
+        let mut attempts = 0;
+        while loops < self.extra_loops && attempts < self.extra_loops * 8 {
+            attempts += 1;
+            let coordinate = (
+                rng.gen_range(0, self.columns.max(1) as i32),
+                rng.gen_range(0, self.rows.max(1) as i32),
+            );
+            let direction = DIRECTIONS[rng.gen_range(0, DIRECTIONS.len())];
+            let neighbor = direction.add(coordinate);
+            let already_connected = map
+                .get_room(coordinate)
+                .map_or(true, |room| room.exit_directions().contains(&direction));
+            if self.contains(neighbor) && !already_connected {
+                map.connect(coordinate, direction, width, height);
+                loops += 1;
+            }
+        }
+        map
+    }
+}
+
+/// Binary space partitioning layout.
+///
+/// The area is split recursively into smaller areas until they reach the
+/// minimum size.  Each area gets one room and sibling areas are connected
+/// by corridors of rooms.
+pub struct BspLayout {
+    pub columns: u32,
+    pub rows: u32,
+    pub min_area_size: u32,
+}
+
+/// Area of the grid as (x, y, width, height).
+type Area = (i32, i32, i32, i32);
+
+impl BspLayout {
+    /// Split the area and connect the parts.
+    ///
+    /// Returns the coordinate of one room inside the area.
+    fn split(
+        &self,
+        map: &mut Map<RoomGeneration>,
+        rng: &mut dyn RngCore,
+        area: Area,
+        width: usize,
+        height: usize,
+    ) -> Coordinate {
+        let (x, y, area_width, area_height) = area;
+        let min_size = self.min_area_size.max(1) as i32;
+        let split_vertical = area_width >= area_height;
+        let length = if split_vertical { area_width } else { area_height };
+        if length < min_size * 2 {
+            let coordinate = (
+                x + rng.gen_range(0, area_width),
+                y + rng.gen_range(0, area_height),
+            );
+            let mut room = RoomGeneration::default();
+            room.width = width;
+            room.height = height;
+            map.get_room_or_insert(coordinate, || room);
+            return coordinate;
+        }
+
+        let position = rng.gen_range(min_size, length - min_size + 1);
+        let (first, second) = if split_vertical {
+            (
+                (x, y, position, area_height),
+                (x + position, y, area_width - position, area_height),
+            )
+        } else {
+            (
+                (x, y, area_width, position),
+                (x, y + position, area_width, area_height - position),
+            )
+        };
+        let first_room = self.split(map, rng, first, width, height);
+        let second_room = self.split(map, rng, second, width, height);
+        connect_with_corridor(map, first_room, second_room, width, height);
+        if rng.gen() {
+            first_room
+        } else {
+            second_room
+        }
+    }
+}
+
+/// Connect two rooms with an L shaped corridor.
+fn connect_with_corridor(map: &mut Map<RoomGeneration>, from: Coordinate, to: Coordinate, width: usize, height: usize) {
+    let mut coordinate = from;
+    while coordinate.0 != to.0 {
+        let direction = if coordinate.0 < to.0 {
+            ExitDirection::East
+        } else {
+            ExitDirection::West
+        };
+        coordinate = map.connect(coordinate, direction, width, height);
+    }
+    while coordinate.1 != to.1 {
+        let direction = if coordinate.1 < to.1 {
+            ExitDirection::South
+        } else {
+            ExitDirection::North
+        };
+        coordinate = map.connect(coordinate, direction, width, height);
+    }
+}
+
+impl LayoutGenerator for BspLayout {
+    fn generate(&self, rng: &mut dyn RngCore, width: usize, height: usize) -> Map<RoomGeneration> {
+        let mut map = Map::new();
+        let area = (0, 0, self.columns.max(1) as i32, self.rows.max(1) as i32);
+        let room = self.split(&mut map, rng, area, width, height);
+        connect_with_corridor(&mut map, (0, 0), room, width, height);
+        map
+    }
+}
+
+/// One long path with short branches on the side.
+pub struct LinearLayout {
+    pub length: u32,
+    pub branches: u32,
+    pub branch_length: u32,
+}
+
+/// Walk up to `length` steps from the start into rooms which don't exist yet.
+///
+/// Returns all rooms of the walk including the start.
+fn random_walk(
+    map: &mut Map<RoomGeneration>,
+    rng: &mut dyn RngCore,
+    start: Coordinate,
+    length: u32,
+    width: usize,
+    height: usize,
+) -> Vec<Coordinate> {
+    let mut path = vec![start];
+    let mut coordinate = start;
+    for _ in 0..length {
+        let candidates: Vec<ExitDirection> = DIRECTIONS
+            .iter()
+            .cloned()
+            .filter(|direction| !map.has_room(direction.add(coordinate)))
+            .collect();
+        if candidates.is_empty() {
+            break;
+        }
+        let direction = candidates[rng.gen_range(0, candidates.len())];
+        coordinate = map.connect(coordinate, direction, width, height);
+        path.push(coordinate);
+    }
+    path
+}
+
+impl LayoutGenerator for LinearLayout {
+    fn generate(&self, rng: &mut dyn RngCore, width: usize, height: usize) -> Map<RoomGeneration> {
+        let mut map = Map::new();
+        let mut room = RoomGeneration::default();
+        room.width = width;
+        room.height = height;
+        map.add_room((0, 0), room);
+
+        let path = random_walk(&mut map, rng, (0, 0), self.length, width, height);
+        for _ in 0..self.branches {
+            let start = path[rng.gen_range(0, path.len())];
+            random_walk(&mut map, rng, start, self.branch_length, width, height);
+        }
+        map
+    }
+}
+
+/// Layout selection in the game config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayoutConfig {
+    RandomWalk {
+        corridor_length: u32,
+        splits: u32,
+    },
+    Grid {
+        columns: u32,
+        rows: u32,
+        extra_loops: u32,
+    },
+    Bsp {
+        columns: u32,
+        rows: u32,
+        min_area_size: u32,
+    },
+    Linear {
+        length: u32,
+        branches: u32,
+        branch_length: u32,
+    },
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        LayoutConfig::RandomWalk {
+            corridor_length: 5,
+            splits: 4,
+        }
+    }
+}
+
+impl LayoutConfig {
+    /// Default settings for the layout with the given name.
+    ///
+    /// Known names are `random-walk`, `grid`, `bsp` and `linear`.
+    pub fn from_name(name: &str) -> Option<LayoutConfig> {
+        match name {
+            "random-walk" => Some(LayoutConfig::default()),
+            "grid" => Some(LayoutConfig::Grid {
+                columns: 4,
+                rows: 3,
+                extra_loops: 2,
+            }),
+            "bsp" => Some(LayoutConfig::Bsp {
+                columns: 6,
+                rows: 6,
+                min_area_size: 2,
+            }),
+            "linear" => Some(LayoutConfig::Linear {
+                length: 8,
+                branches: 3,
+                branch_length: 2,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn generator(&self) -> Box<dyn LayoutGenerator> {
+        match *self {
+            LayoutConfig::RandomWalk {
+                corridor_length,
+                splits,
+            } => Box::new(DungeonGen {
+                corridor_length,
+                splits,
+            }),
+            LayoutConfig::Grid {
+                columns,
+                rows,
+                extra_loops,
+            } => Box::new(GridLayout {
+                columns,
+                rows,
+                extra_loops,
+            }),
+            LayoutConfig::Bsp {
+                columns,
+                rows,
+                min_area_size,
+            } => Box::new(BspLayout {
+                columns,
+                rows,
+                min_area_size,
+            }),
+            LayoutConfig::Linear {
+                length,
+                branches,
+                branch_length,
+            } => Box::new(LinearLayout {
+                length,
+                branches,
+                branch_length,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Generate the layout with several seeds.
+    fn generate_maps(layout: &dyn LayoutGenerator) -> Vec<Map<RoomGeneration>> {
+        (0..50)
+            .map(|seed| layout.generate(&mut StdRng::seed_from_u64(seed), 20, 15))
+            .collect()
+    }
+
+    #[test]
+    fn random_walk_is_connected() {
+        let layout = DungeonGen {
+            corridor_length: 5,
+            splits: 4,
+        };
+        for map in generate_maps(&layout) {
+            assert!(map.is_connected());
+            assert!(map.has_room((0, 0)));
+            assert!(map.len() >= 2 && map.len() <= 1 + 5 * 4, "{} rooms", map.len());
+        }
+    }
+
+    #[test]
+    fn grid_is_connected_and_full() {
+        let layout = GridLayout {
+            columns: 4,
+            rows: 3,
+            extra_loops: 2,
+        };
+        for map in generate_maps(&layout) {
+            assert!(map.is_connected());
+            assert_eq!(map.len(), 12);
+            assert!(map.iter().all(|(coordinate, _)| layout.contains(coordinate)));
+        }
+    }
+
+    #[test]
+    fn bsp_is_connected_and_inside_the_area() {
+        let layout = BspLayout {
+            columns: 6,
+            rows: 6,
+            min_area_size: 2,
+        };
+        for map in generate_maps(&layout) {
+            assert!(map.is_connected());
+            assert!(map.has_room((0, 0)));
+            /* At least one room for each of the four areas of the first two splits */
+            assert!(map.len() >= 4 && map.len() <= 36, "{} rooms", map.len());
+            assert!(map
+                .iter()
+                .all(|((x, y), _)| x >= 0 && y >= 0 && x < 6 && y < 6));
+        }
+    }
+
+    #[test]
+    fn linear_is_connected() {
+        /* Walks of up to six steps can't get stuck, so the main path is complete */
+        let layout = LinearLayout {
+            length: 6,
+            branches: 3,
+            branch_length: 2,
+        };
+        for map in generate_maps(&layout) {
+            assert!(map.is_connected());
+            assert!(map.len() >= 7 && map.len() <= 7 + 3 * 2, "{} rooms", map.len());
+        }
+    }
+
+    #[test]
+    fn exits_lead_to_existing_rooms() {
+        let layouts: Vec<Box<dyn LayoutGenerator>> = ["random-walk", "grid", "bsp", "linear"]
+            .iter()
+            .map(|name| LayoutConfig::from_name(name).unwrap().generator())
+            .collect();
+        for layout in layouts.iter() {
+            for map in generate_maps(layout.as_ref()) {
+                for (coordinate, room) in map.iter() {
+                    for direction in room.exit_directions() {
+                        let neighbor = map.get_room(direction.add(coordinate)).unwrap();
+                        assert!(neighbor.exit_directions().contains(&direction.reverse()));
+                    }
+                }
+            }
+        }
+    }
+}
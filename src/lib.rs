@@ -0,0 +1,45 @@
+//! Game logic of the sprite game.
+//!
+//! The game binary and the tools share the modules in here.
+
+extern crate amethyst;
+extern crate nalgebra as na;
+extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate log;
+extern crate specs_physics;
+
+pub mod asciimap;
+pub mod behavior;
+pub mod camera;
+pub mod characteranimation;
+pub mod config;
+pub mod geometry;
+pub mod charactermeta;
+pub mod charactermove;
+pub mod playerinput;
+pub mod gamerng;
+pub mod replay;
+pub mod damage;
+pub mod health;
+pub mod delayedremove;
+pub mod helper;
+pub mod spriteanimation;
+pub mod spriteanimationloader;
+pub mod spriteimport;
+pub mod swordattack;
+pub mod room;
+pub mod map;
+pub mod layout;
+pub mod mapfile;
+pub mod prefab;
+pub mod pathfinding;
+pub mod visibility;
+pub mod roomexit;
+pub mod roomstate;
+pub mod savegame;
+pub mod game;
+pub mod headless;
+pub mod forces;
+pub mod randomparticles;
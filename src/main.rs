@@ -1,56 +1,71 @@
 extern crate amethyst;
-extern crate nalgebra as na;
-extern crate regex;
-extern crate serde;
 #[macro_use]
 extern crate log;
-extern crate specs_physics;
+extern crate sprite_game;
 
 use amethyst::{
-    input::{InputBundle, StringBindings},
+    input::{InputBundle, InputEvent, StringBindings},
     core::transform::{Transform, TransformBundle},
+    core::timing::Time,
     prelude::*,
     renderer::{
         Camera, RenderToWindow, RenderFlat2D, RenderingBundle,
         types::DefaultBackend,
     },
     utils::application_root_dir,
+    config::Config,
     ecs,
     core::bundle::SystemBundle,
     core::ArcThreadPool,
+    StateEvent,
 };
-use specs_physics:: {
-    systems::*,
+use std::path::PathBuf;
+use sprite_game::{
+    camera::{CameraConfig, CameraFollow, CameraFollowSystem},
+    config, game, health, map, mapfile, playerinput, prefab, randomparticles, replay, savegame,
 };
 
-
-
-pub mod characteranimation;
-pub mod charactermeta;
-pub mod charactermove;
-pub mod damage;
-pub mod delayedremove;
-pub mod helper;
-pub mod spriteanimation;
-pub mod spriteanimationloader;
-pub mod swordattack;
-pub mod room;
-pub mod map;
-pub mod roomexit;
-pub mod forces;
-pub mod randomparticles;
-// pub mod simpleenemy;
-
 struct Example<'a, 'b> {
-    map: map::Map<room::Room>,
-    room_coordinate: map::Coordinate,
-    spawn_player: Option<(i32, i32)>,
+    session: game::GameSession,
+    camera: CameraConfig,
+    save_file: PathBuf,
+    /// Input which is played instead of reading the keyboard.
+    replay_script: Option<playerinput::InputScript>,
+    /// Store the input in this replay file when the game ends.
+    record_replay: Option<String>,
+    /// Duration of every frame while a replay is recorded or played.
+    fixed_timestep: Option<f32>,
 
     dispatcher: Option<ecs::Dispatcher<'a, 'b>>,
 }
 
-pub const ARENA_WIDTH: f32 = 640.0;
-pub const ARENA_HEIGHT: f32 = 480.0;
+impl<'a, 'b> Example<'a, 'b> {
+    /// Build up the current room again after the scene changed.
+    fn reset_room(&self, world: &mut World) {
+        world.delete_all();
+        initialise_camera(world, self.camera);
+        game::populate_room(&self.session, world);
+    }
+
+    fn quick_save(&mut self, world: &mut World) {
+        let save_game = self.session.save_game(world);
+        match savegame::save_game(&save_game, &self.save_file) {
+            Ok(()) => info!("Saved game to {}", self.save_file.display()),
+            Err(error) => warn!("Couldn't save game: {}", error),
+        }
+    }
+
+    fn quick_load(&mut self, world: &mut World) {
+        match savegame::load_game(&self.save_file) {
+            Ok(save_game) => {
+                info!("Loaded game from {}", self.save_file.display());
+                self.session.load_game(save_game);
+                self.reset_room(world);
+            }
+            Err(error) => warn!("Couldn't load game: {}", error),
+        }
+    }
+}
 
 impl<'a, 'b> SimpleState for Example<'a, 'b> {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
@@ -58,8 +73,9 @@ impl<'a, 'b> SimpleState for Example<'a, 'b> {
 
         //world.register::<SpriteRender>();
         //world.register::<Transparent>();
-
         let app_root = application_root_dir().unwrap();
+        game::setup_world(&self.session, world, &app_root);
+
         //let path = format!("{}/resources/display_config.ron", root_dir);
         let binding_path = app_root.join("resources/binding_config.ron");
         let display_config_path = app_root.join("resources/display_config.ron");
@@ -68,57 +84,28 @@ impl<'a, 'b> SimpleState for Example<'a, 'b> {
 
         let mut dispatcher_builder = ecs::DispatcherBuilder::new();
         input_bundle.build(world, &mut dispatcher_builder).unwrap();
+        if let Some(script) = self.replay_script.take() {
+            world.insert(playerinput::ScriptedInput::new(script));
+            dispatcher_builder.add(playerinput::ScriptedInputSystem, "player_input", &["input_system"]);
+        } else {
+            dispatcher_builder.add(playerinput::PlayerInputSystem, "player_input", &["input_system"]);
+        }
+        if self.record_replay.is_some() {
+            dispatcher_builder.add(playerinput::RecordInputSystem, "record_input", &["player_input"]);
+        }
         //.with_bundle(input_bundle)?
         //.with(physics::PhysicsSystem, "physics", &[])
-        let mut dispatcher_builder =
-                dispatcher_builder.with(delayedremove::DelayedRemoveSystem, "delayed_remove", &[])
-            .with(
-                spriteanimation::SpriteAnimationSystem,
-                "sprite_animation",
-                &[],
-            )
+        let geometry = self.session.geometry;
+        let mut dispatcher_builder = game::add_game_systems(dispatcher_builder, world)
             .with(randomparticles::SpawnParticleSystem {
                 average_part_spawn: 0.01,
                 min_x: 0.0,
-                max_x: 640.0,
+                max_x: geometry.arena_width(),
                 min_y: 0.0,
-                max_y: 480.0,
+                max_y: geometry.arena_height(),
                 lifespan: 5.0,
             }, "spawn_particle_system", &[])
-            .with(forces::ForceSystem, "force_system", &[])
-            .with(charactermove::CharacterMoveSystem::default(), "character_move", &[])
-            .with(
-                characteranimation::CharacterAnimationSystem,
-                "character_animation",
-                &["sprite_animation", "character_move"],
-            )
-            
-            .with(SyncBodiesToPhysicsSystem::<f32, Transform>::default(),
-                "sync_bodies_to_physics_system",
-                &["character_move", "delayed_remove"],
-            )
-            .with(SyncCollidersToPhysicsSystem::<f32, Transform>::default(),
-                "sync_colliders_to_physics_system",
-                &["sync_bodies_to_physics_system"],
-            )
-            .with(SyncParametersToPhysicsSystem::<f32>::default(),
-                "sync_gravity_to_physics_system",
-                &[],
-            )
-            .with(PhysicsStepperSystem::<f32>::default(),
-                "physics_stepper_system",
-                &[
-                    "sync_bodies_to_physics_system",
-                    "sync_colliders_to_physics_system",
-                    "sync_gravity_to_physics_system",
-                ],
-            )
-            .with(SyncBodiesFromPhysicsSystem::<f32, Transform>::default(),
-                "sync_bodies_from_physics_system",
-                &["physics_stepper_system"],
-            )
-            .with(roomexit::RoomExitSystem::new(world), "roomexit", &["sync_bodies_from_physics_system"])
-            .with(damage::DestroySystem::default(), "destroy", &["sync_bodies_from_physics_system"]);
+            .with(CameraFollowSystem, "camera_follow", &["sync_bodies_from_physics_system"]);
         RenderingBundle::<DefaultBackend>::new()
                 .with_plugin(
                     RenderToWindow::from_config_path(display_config_path).unwrap()
@@ -134,142 +121,110 @@ impl<'a, 'b> SimpleState for Example<'a, 'b> {
         self.dispatcher = Some(dispatcher);
 
         info!("Initialize camera");
-        initialise_camera(world);
+        initialise_camera(world, self.camera);
         info!("Initialize sprites");
-        initialize_test_sprite(self, world);
+        game::populate_room(&self.session, world);
     }
 
-    fn update(&mut self, game_state: &mut StateData<GameData>) -> SimpleTrans {
-        use crate::roomexit::PerformRoomExit;
+    fn handle_event(&mut self, data: StateData<'_, GameData<'_, '_>>, event: StateEvent) -> SimpleTrans {
+        if let StateEvent::Input(InputEvent::ActionPressed(action)) = &event {
+            match action.as_str() {
+                "quick_save" => self.quick_save(data.world),
+                "quick_load" if self.fixed_timestep.is_some() => {
+                    warn!("Quick load is not possible while a replay is recorded or played");
+                }
+                "quick_load" => self.quick_load(data.world),
+                _ => {}
+            }
+        }
+        SimpleTrans::None
+    }
 
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let (Some(record_replay), Some(timestep)) = (&self.record_replay, self.fixed_timestep) {
+            let frames = std::mem::take(
+                &mut data.world.write_resource::<playerinput::InputRecording>().frames,
+            );
+            let replay = replay::Replay::new(
+                self.session.seed,
+                timestep,
+                self.session.geometry,
+                &self.session.map,
+                frames,
+            );
+            match replay::save_replay(&replay, record_replay) {
+                Ok(()) => info!("Saved replay to {}", record_replay),
+                Err(error) => warn!("Couldn't save replay: {}", error),
+            }
+        }
+    }
+
+    fn update(&mut self, game_state: &mut StateData<GameData>) -> SimpleTrans {
+        if let Some(timestep) = self.fixed_timestep {
+            game_state.world.write_resource::<Time>().set_delta_seconds(timestep);
+        }
         if let Some(dispatcher) = self.dispatcher.as_mut() {
             dispatcher.dispatch(&game_state.world);
         }
 
-
-        let reset_all = {
-            let mut perform_room_exits = game_state.world.fetch_mut::<Option<roomexit::PerformRoomExit>>();
-            if let Some(PerformRoomExit(dest_room, spawn_coordinates)) = &*perform_room_exits {
-                let room_coordinate = dest_room.to_absolute_coordinates(self.room_coordinate);
-                println!("New coordinate: {:?}", room_coordinate);
-                self.room_coordinate = room_coordinate;
-                self.spawn_player = Some(*spawn_coordinates);
-                *perform_room_exits = None;
-                
-                true
-            } else {
-                false
-            }
-        };
-        if reset_all {
-            game_state.world.delete_all();
-            initialise_camera(game_state.world);
-            initialize_test_sprite(self, game_state.world);
+        if game_state.world.fetch_mut::<Option<health::GameOver>>().take().is_some() {
+            return Trans::Push(Box::new(GameOverState::default()));
+        }
+        if self.session.handle_room_exit(game_state.world) {
+            self.reset_room(game_state.world);
         }
         SimpleTrans::None
     }
+
+    fn on_resume(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        // Only the game over state is pushed on top of the game.
+        self.session.respawn(data.world);
+        self.reset_room(data.world);
+    }
+}
+
+/// Pause after the player died, afterwards the game continues with a respawn.
+///
+/// The pause doesn't depend on the input, so replays continue the same way.
+#[derive(Default)]
+struct GameOverState {
+    elapsed: f32,
+}
+
+impl SimpleState for GameOverState {
+    fn on_start(&mut self, _data: StateData<'_, GameData<'_, '_>>) {
+        info!("Game over");
+    }
+
+    fn update(&mut self, data: &mut StateData<GameData>) -> SimpleTrans {
+        self.elapsed += data.world.read_resource::<Time>().delta_seconds();
+        if self.elapsed >= GAME_OVER_DURATION {
+            Trans::Pop
+        } else {
+            Trans::None
+        }
+    }
 }
 
+/// Seconds the game over state is shown.
+const GAME_OVER_DURATION: f32 = 3.0;
+
 /// Initialise the camera.
-fn initialise_camera(world: &mut World) {
+///
+/// It starts in the lower left corner and jumps to the player in the
+/// first frame.
+fn initialise_camera(world: &mut World, camera: CameraConfig) {
     let mut transform = Transform::default();
-    transform.set_translation_xyz(ARENA_WIDTH as f32 / 2.0, ARENA_HEIGHT as f32 / 2.0, 1000.0);
+    transform.set_translation_xyz(camera.view_width / 2.0, camera.view_height / 2.0, 1000.0);
 
     world
         .create_entity()
-        .with(Camera::standard_2d(ARENA_WIDTH, ARENA_HEIGHT))
+        .with(Camera::standard_2d(camera.view_width, camera.view_height))
+        .with(CameraFollow::new(camera))
         .with(transform)
         .build();
 }
 
-fn initialize_test_sprite(scene: &Example, world: &mut World) {
-    info!("Loading sprites");
-    let sprite_animations = spriteanimationloader::load_sprites(world, "texture", "tp-export.ron");
-
-    // Generate a room
-    println!("Getting room: {:?}", scene.room_coordinate);
-    let room = scene.map.get_room(scene.room_coordinate).unwrap();
-
-    for (x, y, field) in room.room_field_iterator() {
-        let pixel_pos = (
-            x as f32 * 32.0 + 16.0,
-            y as f32 * 32.0 + 16.0,
-        );
-        
-        match field {
-            room::RoomField::Nothing => {},
-            room::RoomField::Wall => {
-                // Add a brick
-                helper::create_solid(
-                    world.create_entity(),
-                    &sprite_animations,
-                    pixel_pos,
-                    "brick",
-                ).build();
-            },
-            room::RoomField::Stone => {
-                // Add a stone
-                helper::create_solid(
-                    world.create_entity(),
-                    &sprite_animations,
-                    pixel_pos,
-                    "stones",
-                ).build();
-            },
-            room::RoomField::Bush => {
-                // Add a bush
-                helper::create_solid(
-                    world.create_entity(),
-                    &sprite_animations,
-                    pixel_pos,
-                    "bush",
-                ).with(damage::Destroyable { health: 2.0 })
-                .build();
-            },
-            room::RoomField::Player => {
-                if let None = scene.spawn_player {
-                    helper::create_character(
-                        world.create_entity(),
-                        &sprite_animations,
-                        pixel_pos,
-                        "healer",
-                    )
-                    .with(charactermove::UserMove)
-                    // .with(damage::Destroyer { damage: 1.0})
-                    .build();
-                }
-            },
-            room::RoomField::Exit(direction) => {
-                helper::create_walkable_solid(
-                    world.create_entity(),
-                    pixel_pos,
-                )
-                .with(direction)
-                // .with(damage::Destroyer { damage: 1.0})
-                .build();
-            },
-        }
-    }
-    if let Some(player_coordinate) = scene.spawn_player {
-        info!("Setting player coordinates");
-        let pixel_pos = (
-            player_coordinate.0 as f32 * 32.0 + 16.0,
-            player_coordinate.1 as f32 * 32.0 + 16.0,
-        );
-        helper::create_character(
-            world.create_entity(),
-            &sprite_animations,
-            pixel_pos,
-            "healer",
-        )
-        .with(charactermove::UserMove)
-        // .with(damage::Destroyer { damage: 1.0})
-        .build();
-    }
-    world.insert(sprite_animations);
-    info!("Room setup complete");
-}
-
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
     info!("starting up");
@@ -278,13 +233,69 @@ fn main() -> amethyst::Result<()> {
         .with_bundle(TransformBundle::new())?;
 
 
-    info!("Generate map");
-    let tiles_x = ARENA_WIDTH as usize / 32;
-    let tiles_y = ARENA_HEIGHT as usize / 32;
+    let app_root = application_root_dir()?;
+    let mut config = config::GameConfig::load(app_root.join("resources/game_config.ron"))
+        .unwrap_or_else(|error| {
+            warn!("Couldn't load game config, using defaults: {}", error);
+            config::GameConfig::default()
+        });
+    config.apply_args(std::env::args().skip(1));
+    let replay = match &config.replay {
+        Some(replay_file) => {
+            info!("Play replay {}", replay_file);
+            Some(replay::load_replay(replay_file)?)
+        }
+        None => None,
+    };
+    let seed = replay
+        .as_ref()
+        .map_or_else(|| config.seed_or_random(), |replay| replay.seed);
+    info!("Dungeon seed: {}", seed);
+
+    let mut replay_script = None;
+    let mut fixed_timestep = config.record_replay.as_ref().map(|_| game::FIXED_TIMESTEP);
+    let mut geometry = config.geometry;
+    let map = if let Some(replay) = replay {
+        fixed_timestep = Some(replay.timestep);
+        geometry = replay.geometry;
+        let (map, script) = replay.into_parts()?;
+        replay_script = Some(script);
+        map
+    } else if let Some(map_file) = &config.map_file {
+        info!("Load map from {}", map_file);
+        mapfile::load_map(map_file)?
+    } else {
+        info!("Generate map");
+        let prefabs = config
+            .prefab_file
+            .as_ref()
+            .map(|prefab_file| prefab::load_prefabs(app_root.join(prefab_file)))
+            .unwrap_or_default();
+        map::generate_dungeon(
+            seed,
+            config.layout.generator().as_ref(),
+            &prefabs,
+            geometry.room_width,
+            geometry.room_height,
+        )
+    };
+    if let Err(errors) = map.validate() {
+        for error in errors {
+            warn!("Invalid map: {}", error);
+        }
+    }
+    if let Some(save_map) = &config.save_map {
+        info!("Save map to {}", save_map);
+        mapfile::save_map(&map, save_map)?;
+    }
+
     let scene = Example {
-        map: build_map(tiles_x, tiles_y),
-        room_coordinate: (0, 0),
-        spawn_player: None,
+        session: game::GameSession::new(map, seed, geometry),
+        camera: config.camera,
+        save_file: app_root.join(config.save_file()),
+        replay_script,
+        record_replay: config.record_replay.clone(),
+        fixed_timestep,
         dispatcher: None,
     };
 
@@ -296,28 +307,3 @@ fn main() -> amethyst::Result<()> {
 
     Ok(())
 }
-
-fn build_map(width: usize, height: usize) -> map::Map<room::Room> {
-    /*let mut map = map::Map::new();
-
-    let mut room_generation1 = room::RoomGeneration::default();
-    room_generation1.width = width;
-    room_generation1.height = height;
-    room_generation1.exit_east = true;
-    let room1 = room_generation1.generate_room(&mut rand::thread_rng());
-
-    let mut room_generation2 = room::RoomGeneration::default();
-    room_generation2.width = width;
-    room_generation2.height = height;
-    room_generation2.exit_west = true;
-    let room2 = room_generation2.generate_room(&mut rand::thread_rng());
-
-    map.add_room((0, 0), room1);
-    map.add_room((1, 0), room2);*/
-    let mut map_gen = map::DungeonGen::default();
-    map_gen.corridor_length = 5;
-    map_gen.splits = 4;
-    let map = map_gen.generate(&mut rand::thread_rng(), width, height).generate_map(&mut rand::thread_rng());
-
-    map
-}
@@ -1,11 +1,15 @@
-use std::collections::BTreeMap;
-use crate::room::{Room, RoomGeneration};
+use std::collections::{BTreeMap, VecDeque};
+use crate::layout::LayoutGenerator;
+use crate::prefab::PrefabLibrary;
+use crate::room::{DestRoom, ExitDirection, Room, RoomField, RoomGeneration, RoomRole};
 use rand::prelude::*;
-use rand::distributions::Standard;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub type Coordinate = (i32, i32);
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Map<T> {
     rooms: BTreeMap<Coordinate, T>
 }
@@ -38,64 +42,131 @@ impl<T> Map<T> {
     pub fn get_room_or_insert(&mut self, coordinate: Coordinate, f: impl FnOnce() -> T) -> &mut T {
         self.rooms.entry(coordinate).or_insert_with(f)
     }
+
+    /// Iterate over all rooms ordered by their coordinate.
+    pub fn iter(&self) -> impl Iterator<Item = (Coordinate, &T)> {
+        self.rooms.iter().map(|(coordinate, room)| (*coordinate, room))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
 }
 
-#[derive(Default)]
-pub struct DungeonGen {
-    pub corridor_length: u32,
-    pub splits: u32,
+/// Rooms which know where their exits lead to.
+pub trait RoomConnections {
+    /// Coordinates of the rooms the exits lead to if the room is at the given coordinate.
+    fn exit_targets(&self, coordinate: Coordinate) -> Vec<Coordinate>;
 }
 
-enum Direction {
-    North, South, East, West,
+impl RoomConnections for RoomGeneration {
+    fn exit_targets(&self, coordinate: Coordinate) -> Vec<Coordinate> {
+        self.exit_directions()
+            .iter()
+            .map(|direction| direction.add(coordinate))
+            .collect()
+    }
 }
-impl Distribution<Direction> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
-        match rng.gen_range(0, 4) {
-            0 => Direction::North,
-            1 => Direction::South,
-            2 => Direction::East,
-            3 => Direction::West,
 
-            // Not possible but the compiler doesn't know that.
-            _ => Direction::North
-        }
+impl RoomConnections for Room {
+    fn exit_targets(&self, coordinate: Coordinate) -> Vec<Coordinate> {
+        self.room_field_iterator()
+            .filter_map(|(_, _, field)| match field {
+                RoomField::Exit(dest_room) => Some(dest_room.to_absolute_coordinates(coordinate)),
+                _ => None,
+            })
+            .collect()
     }
 }
 
-impl Direction {
-    fn relative_pos(&self) -> (i32, i32) {
-        match self {
-            Direction::North => (0, -1),
-            Direction::South => (0, 1),
-            Direction::East => (1, 0),
-            Direction::West => (-1, 0),
+impl<T: RoomConnections> Map<T> {
+    /// Existing rooms which can be reached through an exit of the room.
+    pub fn neighbors(&self, coordinate: Coordinate) -> Vec<Coordinate> {
+        let mut neighbors: Vec<Coordinate> = self
+            .get_room(coordinate)
+            .map(|room| room.exit_targets(coordinate))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|target| self.has_room(*target))
+            .collect();
+        neighbors.sort();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// Breadth first search from the room.
+    ///
+    /// Returns the predecessor on the shortest path and the distance for
+    /// every reachable room.
+    fn search(&self, from: Coordinate) -> BTreeMap<Coordinate, (Coordinate, usize)> {
+        let mut visited = BTreeMap::new();
+        if !self.has_room(from) {
+            return visited;
+        }
+        visited.insert(from, (from, 0));
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(coordinate) = queue.pop_front() {
+            let distance = visited[&coordinate].1;
+            for neighbor in self.neighbors(coordinate) {
+                if !visited.contains_key(&neighbor) {
+                    visited.insert(neighbor, (coordinate, distance + 1));
+                    queue.push_back(neighbor);
+                }
+            }
         }
+        visited
     }
 
-    fn add(&self, coordinates: (i32, i32)) -> (i32, i32) {
-        let relative_pos = self.relative_pos();
-        (relative_pos.0 + coordinates.0, relative_pos.1 + coordinates.1)
+    /// Number of room transitions from the room to every reachable room.
+    pub fn distances(&self, from: Coordinate) -> BTreeMap<Coordinate, usize> {
+        self.search(from)
+            .into_iter()
+            .map(|(coordinate, (_, distance))| (coordinate, distance))
+            .collect()
     }
 
-    fn set_exit(&self, room_gen: &mut RoomGeneration) {
-        match self {
-            Direction::North => room_gen.exit_north = true,
-            Direction::South => room_gen.exit_south = true,
-            Direction::East => room_gen.exit_east = true,
-            Direction::West => room_gen.exit_west = true,
+    /// Number of room transitions between two rooms.
+    pub fn distance(&self, from: Coordinate, to: Coordinate) -> Option<usize> {
+        self.search(from).get(&to).map(|(_, distance)| *distance)
+    }
+
+    /// Shortest path between two rooms including both of them.
+    pub fn path(&self, from: Coordinate, to: Coordinate) -> Option<Vec<Coordinate>> {
+        let visited = self.search(from);
+        let mut coordinate = to;
+        let mut path = vec![to];
+        while coordinate != from {
+            coordinate = visited.get(&coordinate)?.0;
+            path.push(coordinate);
         }
+        path.reverse();
+        Some(path)
     }
 
-    fn reverse(&self) -> Direction {
-        match self {
-            Direction::North => Direction::South,
-            Direction::South => Direction::North,
-            Direction::East => Direction::West,
-            Direction::West => Direction::East,
+    /// Check if every room can be reached from the first room.
+    pub fn is_connected(&self) -> bool {
+        match self.rooms.keys().next() {
+            Some(start) => self.search(*start).len() == self.rooms.len(),
+            None => true,
         }
     }
 }
+
+/// Random walk layout.
+///
+/// Digs corridors of rooms starting at (0, 0).  After each corridor it
+/// goes back a random number of rooms and splits off a new corridor.
+#[derive(Default)]
+pub struct DungeonGen {
+    pub corridor_length: u32,
+    pub splits: u32,
+}
+
 fn generate_corridor(map: &mut Map<RoomGeneration>, rng: &mut impl Rng, width: usize, height: usize, corridor_length: u32, mut coordinate: (i32, i32)) -> Vec<(i32,i32)> {
     let mut coordinate_stack = Vec::new();
     for _ in 0..corridor_length {
@@ -106,22 +177,22 @@ fn generate_corridor(map: &mut Map<RoomGeneration>, rng: &mut impl Rng, width: u
                     break None
                 }
                 i += 1;
-                let direction: Direction = rng.gen();
+                let direction: ExitDirection = rng.gen();
                 let new_coordinate = direction.add(coordinate);
-                println!("Map gen: {:?}, {:?}", coordinate, new_coordinate);
+                debug!("Map gen: {:?}, {:?}", coordinate, new_coordinate);
                 if !map.has_room(new_coordinate) {
                     break Some((direction, new_coordinate))
                 }
             }
         };
         if let Some((direction, new_coordinate)) = choice {
-            direction.set_exit(map.get_room_mut(coordinate).unwrap());
+            map.get_room_mut(coordinate).unwrap().set_exit(direction);
             coordinate_stack.push(coordinate);
             coordinate = new_coordinate;
             let mut new_room = RoomGeneration::default();
             new_room.width = width;
             new_room.height = height;
-            direction.reverse().set_exit(&mut new_room);
+            new_room.set_exit(direction.reverse());
             map.add_room(coordinate, new_room);
         }
     }
@@ -142,24 +213,465 @@ impl DungeonGen {
             let mut new_stack = generate_corridor(&mut map, rng, width, height, 
                 self.corridor_length - coordinate_stack.len() as u32, coordinate);
             coordinate_stack.append(&mut new_stack);
-            println!("Stack size before: {}", coordinate_stack.len());
-            for _ in 0..rng.gen_range(0, coordinate_stack.len() - 1) {
-                coordinate_stack.pop();
+            debug!("Stack size before: {}", coordinate_stack.len());
+            if coordinate_stack.len() > 1 {
+                for _ in 0..rng.gen_range(0, coordinate_stack.len() - 1) {
+                    coordinate_stack.pop();
+                }
             }
             coordinate = *coordinate_stack.last().unwrap_or(&(0, 0));
-            println!("Stack size after: {}", coordinate_stack.len());
+            debug!("Stack size after: {}", coordinate_stack.len());
         }
         map
     }
 }
 
 impl Map<RoomGeneration> {
-    pub fn generate_map(&self, rng: &mut impl Rng) -> Map<Room> {
+    /// Open the exits between the room and its neighbor in the given direction.
+    ///
+    /// Missing rooms are created with the given size.  Returns the
+    /// coordinate of the neighbor.
+    pub fn connect(&mut self, coordinate: Coordinate, direction: ExitDirection, width: usize, height: usize) -> Coordinate {
+        let new_room = || {
+            let mut room = RoomGeneration::default();
+            room.width = width;
+            room.height = height;
+            room
+        };
+        let neighbor = direction.add(coordinate);
+        self.get_room_or_insert(coordinate, new_room).set_exit(direction);
+        self.get_room_or_insert(neighbor, new_room).set_exit(direction.reverse());
+        neighbor
+    }
+
+    /// Assign the roles to the rooms.
+    ///
+    /// The start room is the given coordinate, the boss waits in the room
+    /// farthest away from the start and the other dead ends contain
+    /// treasures.  One of the remaining rooms becomes a shop.
+    pub fn assign_roles(&mut self, start: Coordinate, rng: &mut impl Rng) {
+        let distances = self.distances(start);
+        let boss = distances
+            .iter()
+            .filter(|(coordinate, _)| **coordinate != start)
+            .max_by_key(|(_, distance)| **distance)
+            .map(|(coordinate, _)| *coordinate);
+        let dead_ends: Vec<Coordinate> = self
+            .rooms
+            .keys()
+            .cloned()
+            .filter(|coordinate| {
+                *coordinate != start && Some(*coordinate) != boss && self.neighbors(*coordinate).len() == 1
+            })
+            .collect();
+        let others: Vec<Coordinate> = self
+            .rooms
+            .keys()
+            .cloned()
+            .filter(|coordinate| {
+                *coordinate != start && Some(*coordinate) != boss && !dead_ends.contains(coordinate)
+            })
+            .collect();
+        let shop = if others.is_empty() {
+            None
+        } else {
+            Some(others[rng.gen_range(0, others.len())])
+        };
+
+        for (coordinate, room) in self.rooms.iter_mut() {
+            room.role = if *coordinate == start {
+                RoomRole::Start
+            } else if Some(*coordinate) == boss {
+                RoomRole::Boss
+            } else if dead_ends.contains(coordinate) {
+                RoomRole::Treasure
+            } else if Some(*coordinate) == shop {
+                RoomRole::Shop
+            } else {
+                RoomRole::Normal
+            };
+        }
+    }
+
+    /// Generate the content of all rooms.
+    ///
+    /// A fitting prefab is used for normal rooms if the library offers
+    /// one, otherwise the room is generated randomly.
+    pub fn generate_map(&self, rng: &mut impl Rng, prefabs: &PrefabLibrary) -> Map<Room> {
         let mut map = Map::new();
         for key in self.rooms.keys() {
             let value = self.rooms.get(key).unwrap();
-            map.add_room(*key, value.generate_room(rng));
+            let prefab = if value.role == RoomRole::Normal {
+                prefabs.choose(value, rng)
+            } else {
+                None
+            };
+            let room = prefab
+                .and_then(|prefab| value.generate_room_from_prefab(&prefab.room, rng))
+                .unwrap_or_else(|| value.generate_room(rng));
+            map.add_room(*key, room);
         }
         map
     }
 }
+
+/// Problems found by `Map::<Room>::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapValidationError {
+    /// The exit leads to a missing room or to a field which is no exit.
+    DanglingExit {
+        room: Coordinate,
+        exit: (usize, usize),
+    },
+    /// The paired exit doesn't lead back to the exit.
+    OneWayDoor {
+        room: Coordinate,
+        exit: (usize, usize),
+        dest_room: Coordinate,
+        dest_exit: (i32, i32),
+    },
+    /// The player would arrive on a solid field or outside of the room.
+    SpawnOnSolid {
+        room: Coordinate,
+        exit: (usize, usize),
+        dest_room: Coordinate,
+        spawn: Option<(usize, usize)>,
+    },
+}
+
+impl fmt::Display for MapValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapValidationError::DanglingExit { room, exit } => write!(
+                f,
+                "Exit {:?} in room {:?} leads nowhere",
+                exit, room
+            ),
+            MapValidationError::OneWayDoor {
+                room,
+                exit,
+                dest_room,
+                dest_exit,
+            } => write!(
+                f,
+                "Exit {:?} in room {:?} leads to exit {:?} in room {:?} which doesn't lead back",
+                exit, room, dest_exit, dest_room
+            ),
+            MapValidationError::SpawnOnSolid {
+                room,
+                exit,
+                dest_room,
+                spawn,
+            } => write!(
+                f,
+                "Exit {:?} in room {:?} spawns the player on a solid field {:?} in room {:?}",
+                exit, room, spawn, dest_room
+            ),
+        }
+    }
+}
+
+impl Map<Room> {
+    /// Coordinate of the start room.
+    ///
+    /// Falls back to (0, 0) for maps without roles.
+    pub fn start_room(&self) -> Coordinate {
+        self.iter()
+            .find(|(_, room)| room.role == RoomRole::Start)
+            .map(|(coordinate, _)| coordinate)
+            .unwrap_or((0, 0))
+    }
+
+    /// Find the paired exit of an exit in the destination room.
+    ///
+    /// Returns the destination room coordinate and the field of the paired
+    /// exit if it exists.
+    pub fn paired_exit(&self, room: Coordinate, dest_room: DestRoom) -> Option<(Coordinate, (usize, usize))> {
+        let dest_coordinate = dest_room.to_absolute_coordinates(room);
+        let dest = self.get_room(dest_coordinate)?;
+        let (x, y) = dest_room.dest_exit();
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let dest_exit = (x as usize, y as usize);
+        match dest.get_field(dest_exit.0, dest_exit.1) {
+            Some(RoomField::Exit(_)) => Some((dest_coordinate, dest_exit)),
+            _ => None,
+        }
+    }
+
+    /// Field where the player arrives after taking an exit in the given room.
+    pub fn spawn_point(&self, room: Coordinate, dest_room: DestRoom) -> Option<(i32, i32)> {
+        let (dest_coordinate, dest_exit) = self.paired_exit(room, dest_room)?;
+        self.get_room(dest_coordinate)?
+            .entry_point(dest_exit)
+            .map(|(x, y)| (x as i32, y as i32))
+    }
+
+    /// Check that all exits are paired and lead to a free field.
+    pub fn validate(&self) -> Result<(), Vec<MapValidationError>> {
+        let mut errors = Vec::new();
+        for (coordinate, room) in self.iter() {
+            for (x, y, field) in room.room_field_iterator() {
+                let dest_room = match field {
+                    RoomField::Exit(dest_room) => dest_room,
+                    _ => continue,
+                };
+                let exit = (x, y);
+                let (dest_coordinate, dest_exit) = match self.paired_exit(coordinate, dest_room) {
+                    Some(paired_exit) => paired_exit,
+                    None => {
+                        errors.push(MapValidationError::DanglingExit { room: coordinate, exit });
+                        continue;
+                    }
+                };
+                let dest = self.get_room(dest_coordinate).unwrap();
+
+                if let Some(RoomField::Exit(back)) = dest.get_field(dest_exit.0, dest_exit.1) {
+                    let leads_back = back.to_absolute_coordinates(dest_coordinate) == coordinate
+                        && back.dest_exit() == (x as i32, y as i32);
+                    if !leads_back {
+                        errors.push(MapValidationError::OneWayDoor {
+                            room: coordinate,
+                            exit,
+                            dest_room: dest_coordinate,
+                            dest_exit: back.dest_exit(),
+                        });
+                    }
+                }
+
+                let spawn = dest.entry_point(dest_exit);
+                let spawn_is_free = spawn
+                    .and_then(|(spawn_x, spawn_y)| dest.get_field(spawn_x, spawn_y))
+                    .map_or(false, |spawn_field| !spawn_field.is_solid());
+                if !spawn_is_free {
+                    errors.push(MapValidationError::SpawnOnSolid {
+                        room: coordinate,
+                        exit,
+                        dest_room: dest_coordinate,
+                        spawn,
+                    });
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Generate the dungeon.
+///
+/// The whole generation draws from one RNG seeded with `seed`, so the same
+/// seed always results in the same map.
+pub fn generate_dungeon(
+    seed: u64,
+    layout: &dyn LayoutGenerator,
+    prefabs: &PrefabLibrary,
+    width: usize,
+    height: usize,
+) -> Map<Room> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut layout_map = layout.generate(&mut rng, width, height);
+    layout_map.assign_roles((0, 0), &mut rng);
+    layout_map.generate_map(&mut rng, prefabs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LayoutConfig;
+    use crate::mapfile::MapFile;
+
+    fn serialize(map: &Map<Room>) -> String {
+        ron::ser::to_string(&MapFile::from_map(map)).unwrap()
+    }
+
+    #[test]
+    fn same_seed_generates_same_map() {
+        let prefabs = PrefabLibrary::default();
+        for name in ["random-walk", "grid", "bsp", "linear"].iter() {
+            let layout = LayoutConfig::from_name(name).unwrap().generator();
+            for seed in 0..20 {
+                let first = generate_dungeon(seed, layout.as_ref(), &prefabs, 20, 15);
+                let second = generate_dungeon(seed, layout.as_ref(), &prefabs, 20, 15);
+                assert_eq!(serialize(&first), serialize(&second), "{} with seed {}", name, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_maps() {
+        let prefabs = PrefabLibrary::default();
+        let layout = DungeonGen { corridor_length: 5, splits: 4 };
+        let first = generate_dungeon(1, &layout, &prefabs, 20, 15);
+        let second = generate_dungeon(2, &layout, &prefabs, 20, 15);
+        assert_ne!(serialize(&first), serialize(&second));
+    }
+
+    /// Walled 6x6 room without exits.
+    fn walled_room() -> Room {
+        let mut room = Room::new(6, 6);
+        for i in 0..6 {
+            room.set_field(i, 0, RoomField::Wall);
+            room.set_field(i, 5, RoomField::Wall);
+            room.set_field(0, i, RoomField::Wall);
+            room.set_field(5, i, RoomField::Wall);
+        }
+        room
+    }
+
+    /// Two rooms next to each other, connected by a pair of exits.
+    fn connected_rooms() -> Map<Room> {
+        let mut west = walled_room();
+        west.set_field(5, 3, RoomField::Exit(DestRoom::Relative(1, 0, 0, 3)));
+        let mut east = walled_room();
+        east.set_field(0, 3, RoomField::Exit(DestRoom::Relative(-1, 0, 5, 3)));
+        let mut map = Map::new();
+        map.add_room((0, 0), west);
+        map.add_room((1, 0), east);
+        map
+    }
+
+    #[test]
+    fn paired_exits_are_valid() {
+        assert_eq!(connected_rooms().validate(), Ok(()));
+    }
+
+    #[test]
+    fn exit_to_missing_room_is_dangling() {
+        let mut map = connected_rooms();
+        map.get_room_mut((0, 0))
+            .unwrap()
+            .set_field(3, 5, RoomField::Exit(DestRoom::Relative(0, 1, 3, 0)));
+        assert_eq!(
+            map.validate(),
+            Err(vec![MapValidationError::DanglingExit { room: (0, 0), exit: (3, 5) }])
+        );
+    }
+
+    #[test]
+    fn exit_which_leads_elsewhere_is_one_way() {
+        let mut map = connected_rooms();
+        // The exit of the east room leads back to this second exit.
+        let west = map.get_room_mut((0, 0)).unwrap();
+        west.set_field(5, 2, RoomField::Exit(DestRoom::Relative(1, 0, 0, 3)));
+        map.get_room_mut((1, 0))
+            .unwrap()
+            .set_field(0, 3, RoomField::Exit(DestRoom::Relative(-1, 0, 5, 2)));
+        assert_eq!(
+            map.validate(),
+            Err(vec![MapValidationError::OneWayDoor {
+                room: (0, 0),
+                exit: (5, 3),
+                dest_room: (1, 0),
+                dest_exit: (5, 2),
+            }])
+        );
+    }
+
+    #[test]
+    fn spawn_on_stone_is_reported() {
+        let mut map = connected_rooms();
+        map.get_room_mut((1, 0)).unwrap().set_field(1, 3, RoomField::Stone);
+        assert_eq!(
+            map.validate(),
+            Err(vec![MapValidationError::SpawnOnSolid {
+                room: (0, 0),
+                exit: (5, 3),
+                dest_room: (1, 0),
+                spawn: Some((1, 3)),
+            }])
+        );
+    }
+
+    #[test]
+    fn generated_dungeons_are_valid() {
+        let prefabs = PrefabLibrary::default();
+        for name in ["random-walk", "grid", "bsp", "linear"].iter() {
+            let layout = LayoutConfig::from_name(name).unwrap().generator();
+            for seed in 0..50 {
+                let map = generate_dungeon(seed, layout.as_ref(), &prefabs, 20, 15);
+                assert_eq!(map.validate(), Ok(()), "{} with seed {}", name, seed);
+            }
+        }
+    }
+
+    /// Small tree of rooms:
+    /// ```text
+    ///         (2,-1)
+    ///           |
+    /// (0,0) - (1,0) - (2,0) - (3,0)
+    ///           |
+    ///         (1,1)
+    /// ```
+    fn room_tree() -> Map<RoomGeneration> {
+        let mut map = Map::new();
+        let east = map.connect((0, 0), ExitDirection::East, 20, 15);
+        let east = map.connect(east, ExitDirection::East, 20, 15);
+        map.connect(east, ExitDirection::East, 20, 15);
+        map.connect((1, 0), ExitDirection::South, 20, 15);
+        map.connect((2, 0), ExitDirection::North, 20, 15);
+        map
+    }
+
+    #[test]
+    fn neighbors_distance_and_path() {
+        let mut map = room_tree();
+        assert_eq!(map.neighbors((1, 0)), vec![(0, 0), (1, 1), (2, 0)]);
+        assert_eq!(map.neighbors((3, 0)), vec![(2, 0)]);
+        assert_eq!(map.distance((0, 0), (3, 0)), Some(3));
+        assert_eq!(map.distance((1, 1), (2, -1)), Some(3));
+        assert_eq!(map.path((0, 0), (3, 0)), Some(vec![(0, 0), (1, 0), (2, 0), (3, 0)]));
+        assert_eq!(map.path((1, 1), (1, 1)), Some(vec![(1, 1)]));
+        assert!(map.is_connected());
+
+        map.add_room((5, 5), RoomGeneration::default());
+        assert_eq!(map.distance((0, 0), (5, 5)), None);
+        assert_eq!(map.path((0, 0), (5, 5)), None);
+        assert_eq!(map.distance((0, 0), (9, 9)), None);
+        assert!(!map.is_connected());
+    }
+
+    #[test]
+    fn roles_of_room_tree() {
+        let mut map = room_tree();
+        map.assign_roles((0, 0), &mut StdRng::seed_from_u64(1));
+        let role = |coordinate| map.get_room(coordinate).unwrap().role;
+        assert_eq!(role((0, 0)), RoomRole::Start);
+        // (3, 0) and (2, -1) are both three rooms away.
+        assert!(role((3, 0)) == RoomRole::Boss || role((2, -1)) == RoomRole::Boss);
+        assert_eq!(role((1, 1)), RoomRole::Treasure);
+        let shops = [(1, 0), (2, 0)]
+            .iter()
+            .filter(|coordinate| role(**coordinate) == RoomRole::Shop)
+            .count();
+        assert_eq!(shops, 1);
+    }
+
+    #[test]
+    fn boss_is_farthest_and_treasures_are_dead_ends() {
+        let layout = DungeonGen { corridor_length: 5, splits: 4 };
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut map = layout.generate(&mut rng, 20, 15);
+            map.assign_roles((0, 0), &mut rng);
+            let distances = map.distances((0, 0));
+            let farthest = distances.values().copied().max().unwrap();
+            for (coordinate, room) in map.iter() {
+                match room.role {
+                    RoomRole::Start => assert_eq!(coordinate, (0, 0)),
+                    RoomRole::Boss => assert_eq!(distances[&coordinate], farthest, "seed {}", seed),
+                    RoomRole::Treasure => assert_eq!(map.neighbors(coordinate).len(), 1, "seed {}", seed),
+                    _ => {}
+                }
+            }
+            if map.len() > 1 {
+                let bosses = map.iter().filter(|(_, room)| room.role == RoomRole::Boss).count();
+                assert_eq!(bosses, 1, "seed {}", seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+//! Save and load generated or hand-authored dungeons.
+//!
+//! A dungeon is stored as a list of rooms with their coordinates.  The
+//! file format is picked by the file extension: `.json` is stored as
+//! JSON, everything else as RON.
+
+use crate::map::{Coordinate, Map};
+use crate::room::Room;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the map file format which is written by this build.
+///
+/// Version 2 changed the last two values of `DestRoom` from the spawn
+/// point to the paired exit in the destination room.
+pub const MAP_FORMAT_VERSION: u32 = 2;
+
+/// Top level structure of a map file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MapFile {
+    pub version: u32,
+    pub rooms: Vec<RoomEntry>,
+}
+
+/// One room of the map file together with its coordinate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomEntry {
+    pub coordinate: Coordinate,
+    pub room: Room,
+}
+
+/// Supported file formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapFormat {
+    Ron,
+    Json,
+}
+
+impl MapFormat {
+    /// Pick the format based on the file extension.
+    pub fn from_path(path: &Path) -> MapFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => MapFormat::Json,
+            _ => MapFormat::Ron,
+        }
+    }
+}
+
+/// Everything which can go wrong while reading or writing a map file.
+#[derive(Debug)]
+pub enum MapFileError {
+    Io(PathBuf, std::io::Error),
+    RonParse(PathBuf, ron::de::Error),
+    RonWrite(ron::ser::Error),
+    Json(PathBuf, serde_json::Error),
+    UnsupportedVersion {
+        found: u32,
+        supported: u32,
+    },
+    FieldCountMismatch {
+        coordinate: Coordinate,
+        width: usize,
+        height: usize,
+        fields: usize,
+    },
+}
+
+impl fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapFileError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            MapFileError::RonParse(path, error) => write!(f, "{}: {}", path.display(), error),
+            MapFileError::RonWrite(error) => write!(f, "Couldn't serialize: {}", error),
+            MapFileError::Json(path, error) => write!(f, "{}: {}", path.display(), error),
+            MapFileError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Unsupported map format version {}, expected {}",
+                found, supported
+            ),
+            MapFileError::FieldCountMismatch {
+                coordinate,
+                width,
+                height,
+                fields,
+            } => write!(
+                f,
+                "Room {:?} is {}x{} and needs {} fields but has {}",
+                coordinate,
+                width,
+                height,
+                width * height,
+                fields
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapFileError {}
+
+impl MapFile {
+    /// Create a map file of the current version from a map.
+    pub fn from_map(map: &Map<Room>) -> MapFile {
+        MapFile {
+            version: MAP_FORMAT_VERSION,
+            rooms: map
+                .iter()
+                .map(|(coordinate, room)| RoomEntry {
+                    coordinate,
+                    room: room.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Verify the file and turn it into a map.
+    pub fn into_map(self) -> Result<Map<Room>, MapFileError> {
+        if self.version != MAP_FORMAT_VERSION {
+            return Err(MapFileError::UnsupportedVersion {
+                found: self.version,
+                supported: MAP_FORMAT_VERSION,
+            });
+        }
+        let mut map = Map::new();
+        for RoomEntry { coordinate, room } in self.rooms {
+            if room.width * room.height != room.fields.len() {
+                return Err(MapFileError::FieldCountMismatch {
+                    coordinate,
+                    width: room.width,
+                    height: room.height,
+                    fields: room.fields.len(),
+                });
+            }
+            map.add_room(coordinate, room);
+        }
+        Ok(map)
+    }
+}
+
+/// Write any serializable data to the given path in the format of its extension.
+pub fn write_file<T: Serialize>(data: &T, path: &Path) -> Result<(), MapFileError> {
+    let content = match MapFormat::from_path(path) {
+        MapFormat::Ron => ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::default())
+            .map_err(MapFileError::RonWrite)?,
+        MapFormat::Json => serde_json::to_string_pretty(data)
+            .map_err(|error| MapFileError::Json(path.to_path_buf(), error))?,
+    };
+    fs::write(path, content).map_err(|error| MapFileError::Io(path.to_path_buf(), error))
+}
+
+/// Read any deserializable data from the given path in the format of its extension.
+pub fn read_file<T: DeserializeOwned>(path: &Path) -> Result<T, MapFileError> {
+    let content =
+        fs::read_to_string(path).map_err(|error| MapFileError::Io(path.to_path_buf(), error))?;
+    match MapFormat::from_path(path) {
+        MapFormat::Ron => ron::de::from_str(&content)
+            .map_err(|error| MapFileError::RonParse(path.to_path_buf(), error)),
+        MapFormat::Json => serde_json::from_str(&content)
+            .map_err(|error| MapFileError::Json(path.to_path_buf(), error)),
+    }
+}
+
+/// Write the map to the given path.
+pub fn save_map(map: &Map<Room>, path: impl AsRef<Path>) -> Result<(), MapFileError> {
+    write_file(&MapFile::from_map(map), path.as_ref())
+}
+
+/// Read a map from the given path.
+pub fn load_map(path: impl AsRef<Path>) -> Result<Map<Room>, MapFileError> {
+    let map_file: MapFile = read_file(path.as_ref())?;
+    map_file.into_map()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{generate_dungeon, DungeonGen};
+    use crate::prefab::PrefabLibrary;
+
+    fn example_map() -> Map<Room> {
+        let layout = DungeonGen { corridor_length: 5, splits: 4 };
+        generate_dungeon(3, &layout, &PrefabLibrary::default(), 20, 15)
+    }
+
+    /// Path in the temp directory which is unique for this test run.
+    fn temp_path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "sprite_game_map_{}_{}.{}",
+            std::process::id(),
+            name,
+            extension
+        ))
+    }
+
+    /// Write the content to a file and try to load it as map.
+    fn load_content(name: &str, extension: &str, content: &str) -> Result<Map<Room>, MapFileError> {
+        let path = temp_path(name, extension);
+        fs::write(&path, content).unwrap();
+        let result = load_map(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn serialize(map_file: &MapFile, extension: &str) -> String {
+        match extension {
+            "json" => serde_json::to_string(map_file).unwrap(),
+            _ => ron::ser::to_string(map_file).unwrap(),
+        }
+    }
+
+    fn round_trip(extension: &str) {
+        let map = example_map();
+        let path = temp_path("round_trip", extension);
+        save_map(&map, &path).unwrap();
+        let loaded = load_map(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(map, loaded.unwrap());
+    }
+
+    #[test]
+    fn ron_map_round_trips() {
+        round_trip("ron");
+    }
+
+    #[test]
+    fn json_map_round_trips() {
+        round_trip("json");
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        for extension in ["ron", "json"].iter() {
+            let mut map_file = MapFile::from_map(&example_map());
+            map_file.version = MAP_FORMAT_VERSION - 1;
+            let content = serialize(&map_file, extension);
+            match load_content("version", extension, &content) {
+                Err(MapFileError::UnsupportedVersion { found, supported }) => {
+                    assert_eq!(found, MAP_FORMAT_VERSION - 1);
+                    assert_eq!(supported, MAP_FORMAT_VERSION);
+                }
+                result => panic!("Expected a version error, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_rejected() {
+        for extension in ["ron", "json"].iter() {
+            let mut map_file = MapFile::from_map(&example_map());
+            let coordinate = map_file.rooms[0].coordinate;
+            map_file.rooms[0].room.fields.pop();
+            let content = serialize(&map_file, extension);
+            match load_content("fields", extension, &content) {
+                Err(MapFileError::FieldCountMismatch { coordinate: found, width, height, fields }) => {
+                    assert_eq!(found, coordinate);
+                    assert_eq!((width, height, fields), (20, 15, 20 * 15 - 1));
+                }
+                result => panic!("Expected a field count error, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_field_kinds_are_rejected() {
+        let map_file = MapFile::from_map(&example_map());
+        let content = serialize(&map_file, "ron").replacen("Wall", "Lava", 1);
+        match load_content("unknown", "ron", &content) {
+            Err(MapFileError::RonParse(_, error)) => assert!(error.to_string().contains("Lava"), "{}", error),
+            result => panic!("Expected a RON error, got {:?}", result),
+        }
+        let content = serialize(&map_file, "json").replacen("\"Wall\"", "\"Lava\"", 1);
+        match load_content("unknown", "json", &content) {
+            Err(MapFileError::Json(_, error)) => assert!(error.to_string().contains("Lava"), "{}", error),
+            result => panic!("Expected a JSON error, got {:?}", result),
+        }
+    }
+}
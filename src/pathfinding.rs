@@ -0,0 +1,301 @@
+//! Find ways through a room on its tile grid.
+//!
+//! Walls, stones and bushes block the way, exits can be walked on.  The
+//! `NavigationGrid` is created from the `Room` when it is populated and is
+//! kept up to date when a bush is destroyed.  `find_path` searches a single
+//! path with A*.  A `FlowField` leads every tile to one target, so many
+//! enemies can chase the player with a single search.
+
+use crate::room::{Room, RoomField};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Tiles which can be walked on in the current room.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NavigationGrid {
+    pub width: usize,
+    pub height: usize,
+    /// True for tiles which can be walked on, same index as `Room::fields`.
+    pub passable: Vec<bool>,
+}
+
+impl NavigationGrid {
+    pub fn from_room(room: &Room) -> Self {
+        NavigationGrid {
+            width: room.width,
+            height: room.height,
+            passable: room.fields.iter().map(is_passable_field).collect(),
+        }
+    }
+
+    pub fn is_passable(&self, (x, y): (usize, usize)) -> bool {
+        x < self.width && y < self.height && self.passable[x + y * self.width]
+    }
+
+    /// Change a tile, for example after its bush was destroyed.
+    pub fn set_passable(&mut self, (x, y): (usize, usize), passable: bool) {
+        if x < self.width && y < self.height {
+            self.passable[x + y * self.width] = passable;
+        }
+    }
+
+    /// Passable tiles next to the given one, without diagonals.
+    pub fn neighbors(&self, (x, y): (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut neighbors = Vec::with_capacity(4);
+        if x > 0 {
+            neighbors.push((x - 1, y));
+        }
+        if x + 1 < self.width {
+            neighbors.push((x + 1, y));
+        }
+        if y > 0 {
+            neighbors.push((x, y - 1));
+        }
+        if y + 1 < self.height {
+            neighbors.push((x, y + 1));
+        }
+        neighbors.into_iter().filter(move |tile| self.is_passable(*tile))
+    }
+
+    /// Shortest path with A*, including the start and the target tile.
+    ///
+    /// Returns None if the target can't be reached or one of the tiles is
+    /// blocked.
+    pub fn find_path(&self, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        if !self.is_passable(from) || !self.is_passable(to) {
+            return None;
+        }
+        let index = |(x, y): (usize, usize)| x + y * self.width;
+        let mut costs = vec![u32::MAX; self.passable.len()];
+        let mut previous = vec![None; self.passable.len()];
+        let mut open = BinaryHeap::new();
+        costs[index(from)] = 0;
+        open.push(OpenTile {
+            estimate: distance(from, to),
+            cost: 0,
+            tile: from,
+        });
+        while let Some(OpenTile { cost, tile, .. }) = open.pop() {
+            if tile == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while let Some(tile) = previous[index(current)] {
+                    path.push(tile);
+                    current = tile;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost > costs[index(tile)] {
+                continue;
+            }
+            for neighbor in self.neighbors(tile) {
+                let neighbor_cost = cost + 1;
+                if neighbor_cost < costs[index(neighbor)] {
+                    costs[index(neighbor)] = neighbor_cost;
+                    previous[index(neighbor)] = Some(tile);
+                    open.push(OpenTile {
+                        estimate: neighbor_cost + distance(neighbor, to),
+                        cost: neighbor_cost,
+                        tile: neighbor,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Steps from every tile to the target.
+    pub fn flow_field(&self, target: (usize, usize)) -> FlowField {
+        let mut distances = vec![None; self.passable.len()];
+        let mut queue = VecDeque::new();
+        if self.is_passable(target) {
+            distances[target.0 + target.1 * self.width] = Some(0);
+            queue.push_back(target);
+        }
+        while let Some(tile) = queue.pop_front() {
+            let next_distance = distances[tile.0 + tile.1 * self.width].unwrap_or(0) + 1;
+            for (x, y) in self.neighbors(tile) {
+                if distances[x + y * self.width].is_none() {
+                    distances[x + y * self.width] = Some(next_distance);
+                    queue.push_back((x, y));
+                }
+            }
+        }
+        FlowField {
+            width: self.width,
+            height: self.height,
+            target,
+            distances,
+        }
+    }
+}
+
+/// Distances from every tile to one target.
+///
+/// It is cheap enough to be made once per frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowField {
+    pub width: usize,
+    pub height: usize,
+    pub target: (usize, usize),
+    /// Steps to the target, None if it can't be reached.
+    pub distances: Vec<Option<u32>>,
+}
+
+impl FlowField {
+    pub fn distance(&self, (x, y): (usize, usize)) -> Option<u32> {
+        if x < self.width && y < self.height {
+            self.distances[x + y * self.width]
+        } else {
+            None
+        }
+    }
+
+    /// Neighbor tile which is closer to the target.
+    ///
+    /// Returns None on the target itself and on tiles which can't reach it.
+    pub fn next_tile(&self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        let current = self.distance((x, y))?;
+        let mut neighbors = Vec::with_capacity(4);
+        if x > 0 {
+            neighbors.push((x - 1, y));
+        }
+        neighbors.push((x + 1, y));
+        if y > 0 {
+            neighbors.push((x, y - 1));
+        }
+        neighbors.push((x, y + 1));
+        neighbors
+            .into_iter()
+            .filter_map(|tile| self.distance(tile).map(|distance| (distance, tile)))
+            .filter(|(distance, _)| *distance < current)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, tile)| tile)
+    }
+}
+
+/// Shortest path between two tiles of a room.
+///
+/// Use a `NavigationGrid` directly to search many paths in the same room.
+pub fn find_path(room: &Room, from: (usize, usize), to: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+    NavigationGrid::from_room(room).find_path(from, to)
+}
+
+/// Fields which can be walked on, bushes are blocked until they are cut.
+pub fn is_passable_field(field: &RoomField) -> bool {
+    match field {
+        RoomField::Wall | RoomField::Stone | RoomField::Bush => false,
+        _ => true,
+    }
+}
+
+/// Manhattan distance, the heuristic for A*.
+fn distance((x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> u32 {
+    ((x1 as i64 - x2 as i64).abs() + (y1 as i64 - y2 as i64).abs()) as u32
+}
+
+/// Entry in the open list of A*, the smallest estimate comes first.
+#[derive(PartialEq, Eq)]
+struct OpenTile {
+    estimate: u32,
+    cost: u32,
+    tile: (usize, usize),
+}
+
+impl Ord for OpenTile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .cmp(&self.estimate)
+            .then_with(|| self.cost.cmp(&other.cost))
+            .then_with(|| other.tile.cmp(&self.tile))
+    }
+}
+
+impl PartialOrd for OpenTile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room::DestRoom;
+
+    /// Room with walls around a free area of 5x3 tiles.
+    fn walled_room() -> Room {
+        let mut room = Room::new(7, 5);
+        for x in 0..7 {
+            room.set_field(x, 0, RoomField::Wall);
+            room.set_field(x, 4, RoomField::Wall);
+        }
+        for y in 0..5 {
+            room.set_field(0, y, RoomField::Wall);
+            room.set_field(6, y, RoomField::Wall);
+        }
+        room
+    }
+
+    fn is_connected(path: &[(usize, usize)]) -> bool {
+        path.windows(2).all(|step| distance(step[0], step[1]) == 1)
+    }
+
+    #[test]
+    fn path_goes_around_a_stone() {
+        let mut room = walled_room();
+        room.set_field(3, 2, RoomField::Stone);
+        let path = find_path(&room, (1, 2), (5, 2)).unwrap();
+        assert_eq!(path.first(), Some(&(1, 2)));
+        assert_eq!(path.last(), Some(&(5, 2)));
+        assert_eq!(path.len(), 7);
+        assert!(!path.contains(&(3, 2)));
+        assert!(is_connected(&path));
+    }
+
+    #[test]
+    fn no_path_when_walled_off() {
+        let mut room = walled_room();
+        for y in 1..4 {
+            room.set_field(3, y, RoomField::Wall);
+        }
+        assert_eq!(find_path(&room, (1, 2), (5, 2)), None);
+        assert_eq!(find_path(&room, (1, 2), (0, 2)), None);
+    }
+
+    #[test]
+    fn exit_is_passable() {
+        let mut room = walled_room();
+        room.set_field(6, 2, RoomField::Exit(DestRoom::Relative(1, 0, 0, 2)));
+        assert_eq!(find_path(&room, (5, 2), (6, 2)), Some(vec![(5, 2), (6, 2)]));
+    }
+
+    #[test]
+    fn bush_blocks_until_it_is_cut() {
+        let mut room = walled_room();
+        room.set_field(3, 1, RoomField::Wall);
+        room.set_field(3, 2, RoomField::Bush);
+        room.set_field(3, 3, RoomField::Wall);
+        let mut grid = NavigationGrid::from_room(&room);
+        assert_eq!(grid.find_path((1, 2), (5, 2)), None);
+
+        grid.set_passable((3, 2), true);
+        let path = grid.find_path((1, 2), (5, 2)).unwrap();
+        assert_eq!(path, vec![(1, 2), (2, 2), (3, 2), (4, 2), (5, 2)]);
+    }
+
+    #[test]
+    fn flow_field_counts_the_steps_to_the_target() {
+        let mut room = walled_room();
+        room.set_field(3, 2, RoomField::Stone);
+        let flow_field = NavigationGrid::from_room(&room).flow_field((1, 2));
+        assert_eq!(flow_field.distance((1, 2)), Some(0));
+        assert_eq!(flow_field.distance((2, 2)), Some(1));
+        assert_eq!(flow_field.distance((4, 2)), Some(5));
+        assert_eq!(flow_field.distance((3, 2)), None);
+        assert_eq!(flow_field.distance((0, 2)), None);
+        assert_eq!(flow_field.next_tile((1, 2)), None);
+        assert_eq!(flow_field.next_tile((2, 2)), Some((1, 2)));
+    }
+}
@@ -0,0 +1,187 @@
+//! Sources for the input which controls the player.
+//!
+//! The `CharacterMoveSystem` only reads the `PlayerInput` resource.  It is
+//! written by exactly one system which is registered as `player_input`:
+//! * `PlayerInputSystem` reads the keyboard through the `InputHandler`.
+//! * `ScriptedInputSystem` plays an `InputScript`, which is used to run
+//!   the game automatically in the headless mode and to play replays.
+//!
+//! The `RecordInputSystem` can be added after the `player_input` system to
+//! record the input for a replay.
+//!
+//! An input script is a RON file like this:
+//! ```text
+//! (
+//!   steps: [
+//!     (duration: 2.0, move_x: 1.0),
+//!     (duration: 0.1, attack: true),
+//!     (duration: 0.5),
+//!   ],
+//! )
+//! ```
+
+use amethyst::{
+    core::timing::Time,
+    ecs::{Read, System, Write},
+    input::{InputHandler, StringBindings},
+};
+use serde::{Deserialize, Serialize};
+
+/// Input state of the player in the current frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerInput {
+    /// Positive to the right, negative to the left.
+    pub move_x: f32,
+    /// Positive to the top, negative to the bottom.
+    pub move_y: f32,
+    pub attack: bool,
+}
+
+/// Copies the state of the keyboard into `PlayerInput`.
+pub struct PlayerInputSystem;
+
+impl<'s> System<'s> for PlayerInputSystem {
+    type SystemData = (
+        Read<'s, InputHandler<StringBindings>>,
+        Write<'s, PlayerInput>,
+    );
+
+    fn run(&mut self, (input, mut player_input): Self::SystemData) {
+        *player_input = PlayerInput {
+            move_x: input.axis_value("player_move_x").unwrap_or(0.0),
+            move_y: input.axis_value("player_move_y").unwrap_or(0.0),
+            attack: input.action_is_down("attack").unwrap_or(false),
+        };
+    }
+}
+
+/// Input which is held for some time.
+///
+/// Everything which is not set in the script is not pressed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputStep {
+    /// Time in seconds.
+    pub duration: f32,
+    pub move_x: f32,
+    pub move_y: f32,
+    pub attack: bool,
+}
+
+impl InputStep {
+    pub fn input(&self) -> PlayerInput {
+        PlayerInput {
+            move_x: self.move_x,
+            move_y: self.move_y,
+            attack: self.attack,
+        }
+    }
+}
+
+/// Timeline of inputs, loaded from a RON file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputScript {
+    pub steps: Vec<InputStep>,
+}
+
+impl InputScript {
+    /// Input at the given time since the start of the script.
+    ///
+    /// After the last step, nothing is pressed anymore.
+    pub fn input_at(&self, time: f32) -> PlayerInput {
+        let mut start = 0.0;
+        for step in self.steps.iter() {
+            if time < start + step.duration {
+                return step.input();
+            }
+            start += step.duration;
+        }
+        PlayerInput::default()
+    }
+
+    /// Total length of the script in seconds.
+    pub fn duration(&self) -> f32 {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+}
+
+/// Script which is currently played by the `ScriptedInputSystem`.
+///
+/// Remembers the current step, so long scripts like replays don't have
+/// to be searched from the start in every frame.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedInput {
+    pub script: InputScript,
+    /// Seconds since the script started.
+    pub elapsed: f32,
+    duration: f32,
+    step: usize,
+    step_start: f32,
+}
+
+impl ScriptedInput {
+    pub fn new(script: InputScript) -> Self {
+        ScriptedInput {
+            duration: script.duration(),
+            script,
+            elapsed: 0.0,
+            step: 0,
+            step_start: 0.0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Input at the current time, see `InputScript::input_at`.
+    pub fn current(&mut self) -> PlayerInput {
+        while let Some(step) = self.script.steps.get(self.step) {
+            if self.elapsed < self.step_start + step.duration {
+                return step.input();
+            }
+            self.step_start += step.duration;
+            self.step += 1;
+        }
+        PlayerInput::default()
+    }
+}
+
+/// Plays the `ScriptedInput` and writes it into `PlayerInput`.
+pub struct ScriptedInputSystem;
+
+impl<'s> System<'s> for ScriptedInputSystem {
+    type SystemData = (
+        Read<'s, Time>,
+        Write<'s, ScriptedInput>,
+        Write<'s, PlayerInput>,
+    );
+
+    fn run(&mut self, (time, mut scripted_input, mut player_input): Self::SystemData) {
+        *player_input = scripted_input.current();
+        scripted_input.elapsed += time.delta_seconds();
+    }
+}
+
+/// Every `PlayerInput` since the recording started, one per frame.
+#[derive(Debug, Clone, Default)]
+pub struct InputRecording {
+    pub frames: Vec<PlayerInput>,
+}
+
+/// Appends the `PlayerInput` of every frame to the `InputRecording`.
+///
+/// Must run after the `player_input` system.
+pub struct RecordInputSystem;
+
+impl<'s> System<'s> for RecordInputSystem {
+    type SystemData = (
+        Read<'s, PlayerInput>,
+        Write<'s, InputRecording>,
+    );
+
+    fn run(&mut self, (player_input, mut recording): Self::SystemData) {
+        recording.frames.push(*player_input);
+    }
+}
@@ -0,0 +1,330 @@
+//! Hand-authored room prefabs.
+//!
+//! Prefabs are drawn as text in a RON file, one string per row with the
+//! north row first.  The characters are the same as in the `asciimap`
+//! room dump:
+//! * `.` Nothing
+//! * `#` Wall
+//! * `s` Stone
+//! * `b` Bush
+//! * `@` Player
+//! * `e` Enemy
+//!
+//! Exits are not drawn, they are opened by the room generation on the
+//! sides listed in `exits`.  Each prefab is also used mirrored and
+//! rotated, so a prefab with a north exit also fits rooms with a south
+//! exit.
+
+use crate::room::{ExitDirection, Room, RoomField, RoomGeneration};
+use amethyst::config::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Definition of one prefab in the RON file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomPrefab {
+    pub name: String,
+    /// Sides where the prefab can have an exit.
+    pub exits: Vec<ExitDirection>,
+    /// Rows of the room, north first.
+    pub tiles: Vec<String>,
+}
+
+/// RON file definition.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefabData {
+    /// Probability to use a prefab if one fits the room.
+    pub chance: f32,
+    pub prefabs: Vec<RoomPrefab>,
+}
+
+impl Default for PrefabData {
+    fn default() -> Self {
+        PrefabData {
+            chance: 0.5,
+            prefabs: Vec::new(),
+        }
+    }
+}
+
+/// Errors in a prefab definition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrefabError {
+    Empty {
+        name: String,
+    },
+    RowLength {
+        name: String,
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownCharacter {
+        name: String,
+        row: usize,
+        column: usize,
+        character: char,
+    },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::Empty { name } => write!(f, "Prefab {} has no tiles", name),
+            PrefabError::RowLength {
+                name,
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Prefab {}: row {} has {} tiles, expected {}",
+                name, row, found, expected
+            ),
+            PrefabError::UnknownCharacter {
+                name,
+                row,
+                column,
+                character,
+            } => write!(
+                f,
+                "Prefab {}: unknown tile '{}' in row {}, column {}",
+                name, character, row, column
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+/// Parse a prefab tile.
+pub fn field_from_char(character: char) -> Option<RoomField> {
+    match character {
+        '.' => Some(RoomField::Nothing),
+        '#' => Some(RoomField::Wall),
+        's' => Some(RoomField::Stone),
+        'b' => Some(RoomField::Bush),
+        '@' => Some(RoomField::Player),
+        'e' => Some(RoomField::Enemy),
+        _ => None,
+    }
+}
+
+impl RoomPrefab {
+    /// Turn the text rows into a room.
+    pub fn to_room(&self) -> Result<Room, PrefabError> {
+        let height = self.tiles.len();
+        let width = self.tiles.first().map_or(0, |row| row.chars().count());
+        if width == 0 {
+            return Err(PrefabError::Empty {
+                name: self.name.clone(),
+            });
+        }
+        let mut room = Room::new(width, height);
+        for (row, line) in self.tiles.iter().enumerate() {
+            let found = line.chars().count();
+            if found != width {
+                return Err(PrefabError::RowLength {
+                    name: self.name.clone(),
+                    row,
+                    expected: width,
+                    found,
+                });
+            }
+            for (column, character) in line.chars().enumerate() {
+                let field = field_from_char(character).ok_or_else(|| PrefabError::UnknownCharacter {
+                    name: self.name.clone(),
+                    row,
+                    column,
+                    character,
+                })?;
+                room.set_field(column, height - 1 - row, field);
+            }
+        }
+        Ok(room)
+    }
+}
+
+/// Mirror or rotation applied to a prefab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefabTransform {
+    Identity,
+    MirrorX,
+    MirrorY,
+    Rotate180,
+    /// Only possible for square prefabs.
+    RotateClockwise,
+    /// Only possible for square prefabs.
+    RotateCounterClockwise,
+}
+
+impl PrefabTransform {
+    fn all() -> [PrefabTransform; 6] {
+        [
+            PrefabTransform::Identity,
+            PrefabTransform::MirrorX,
+            PrefabTransform::MirrorY,
+            PrefabTransform::Rotate180,
+            PrefabTransform::RotateClockwise,
+            PrefabTransform::RotateCounterClockwise,
+        ]
+    }
+
+    fn keeps_size(&self) -> bool {
+        match self {
+            PrefabTransform::RotateClockwise | PrefabTransform::RotateCounterClockwise => false,
+            _ => true,
+        }
+    }
+
+    /// Where the exit direction ends up after the transformation.
+    pub fn direction(&self, direction: ExitDirection) -> ExitDirection {
+        use ExitDirection::*;
+        match (self, direction) {
+            (PrefabTransform::Identity, direction) => direction,
+            (PrefabTransform::MirrorX, East) | (PrefabTransform::MirrorX, West) => direction.reverse(),
+            (PrefabTransform::MirrorX, direction) => direction,
+            (PrefabTransform::MirrorY, North) | (PrefabTransform::MirrorY, South) => direction.reverse(),
+            (PrefabTransform::MirrorY, direction) => direction,
+            (PrefabTransform::Rotate180, direction) => direction.reverse(),
+            (PrefabTransform::RotateClockwise, North) => East,
+            (PrefabTransform::RotateClockwise, East) => South,
+            (PrefabTransform::RotateClockwise, South) => West,
+            (PrefabTransform::RotateClockwise, West) => North,
+            (PrefabTransform::RotateCounterClockwise, direction) => {
+                PrefabTransform::RotateClockwise.direction(direction).reverse()
+            }
+        }
+    }
+
+    /// Apply the transformation to a room.
+    ///
+    /// Returns None if the rotation is not possible because the room is
+    /// not square.
+    pub fn apply(&self, room: &Room) -> Option<Room> {
+        let (width, height) = (room.width, room.height);
+        if !self.keeps_size() && width != height {
+            return None;
+        }
+        let mut result = Room::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (new_x, new_y) = match self {
+                    PrefabTransform::Identity => (x, y),
+                    PrefabTransform::MirrorX => (width - 1 - x, y),
+                    PrefabTransform::MirrorY => (x, height - 1 - y),
+                    PrefabTransform::Rotate180 => (width - 1 - x, height - 1 - y),
+                    PrefabTransform::RotateClockwise => (y, width - 1 - x),
+                    PrefabTransform::RotateCounterClockwise => (height - 1 - y, x),
+                };
+                if let Some(field) = room.get_field(x, y) {
+                    result.set_field(new_x, new_y, field);
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+/// One transformed prefab which is ready to use.
+#[derive(Debug, Clone)]
+pub struct PrefabVariant {
+    pub name: String,
+    pub transform: PrefabTransform,
+    pub exits: Vec<ExitDirection>,
+    pub room: Room,
+}
+
+impl PrefabVariant {
+    /// Check if the variant has the size of the room and supports all its exits.
+    pub fn fits(&self, room_generation: &RoomGeneration) -> bool {
+        self.room.width == room_generation.width
+            && self.room.height == room_generation.height
+            && room_generation
+                .exit_directions()
+                .iter()
+                .all(|direction| self.exits.contains(direction))
+    }
+}
+
+/// All prefabs with all their variants.
+#[derive(Debug, Clone, Default)]
+pub struct PrefabLibrary {
+    pub chance: f32,
+    pub variants: Vec<PrefabVariant>,
+}
+
+impl PrefabLibrary {
+    /// Parse all prefabs and create their variants.
+    pub fn new(data: &PrefabData) -> Result<PrefabLibrary, PrefabError> {
+        let mut variants = Vec::new();
+        for prefab in data.prefabs.iter() {
+            let room = prefab.to_room()?;
+            for transform in PrefabTransform::all().iter() {
+                if let Some(transformed_room) = transform.apply(&room) {
+                    let mut exits: Vec<ExitDirection> = prefab
+                        .exits
+                        .iter()
+                        .map(|direction| transform.direction(*direction))
+                        .collect();
+                    exits.sort_by_key(|direction| *direction as u8);
+                    let duplicate = variants.iter().any(|variant: &PrefabVariant| {
+                        variant.name == prefab.name
+                            && variant.exits == exits
+                            && variant.room == transformed_room
+                    });
+                    if !duplicate {
+                        variants.push(PrefabVariant {
+                            name: prefab.name.clone(),
+                            transform: *transform,
+                            exits,
+                            room: transformed_room,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(PrefabLibrary {
+            chance: data.chance,
+            variants,
+        })
+    }
+
+    /// Pick a random prefab variant for the room.
+    ///
+    /// Returns None if no variant fits or if the dice decided to use the
+    /// random room instead.
+    pub fn choose(&self, room_generation: &RoomGeneration, rng: &mut impl Rng) -> Option<&PrefabVariant> {
+        let candidates: Vec<&PrefabVariant> = self
+            .variants
+            .iter()
+            .filter(|variant| variant.fits(room_generation))
+            .collect();
+        if candidates.is_empty() || rng.gen::<f32>() >= self.chance {
+            return None;
+        }
+        Some(candidates[rng.gen_range(0, candidates.len())])
+    }
+}
+
+/// Load the prefabs from a RON file.
+///
+/// If the file cannot be used, a warning is logged and an empty library is
+/// returned, so all rooms are generated randomly.
+pub fn load_prefabs(path: impl AsRef<Path>) -> PrefabLibrary {
+    let path = path.as_ref();
+    let data = match PrefabData::load(path) {
+        Ok(data) => data,
+        Err(error) => {
+            warn!("Couldn't load prefabs from {}: {}", path.display(), error);
+            return PrefabLibrary::default();
+        }
+    };
+    PrefabLibrary::new(&data).unwrap_or_else(|error| {
+        warn!("Invalid prefab in {}: {}", path.display(), error);
+        PrefabLibrary::default()
+    })
+}
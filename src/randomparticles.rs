@@ -1,13 +1,13 @@
 use amethyst::{
     core::Transform,
     core::timing::Time,
-    ecs::{Entities, System, WriteStorage, Read, ReadExpect},
+    ecs::{Entities, System, WriteStorage, Read, ReadExpect, Write},
     renderer::SpriteRender,
 };
 use specs_physics::{PhysicsBody, PhysicsBodyBuilder, nphysics::object::BodyStatus};
 use crate::spriteanimationloader::SpriteAnimationStore;
 use crate::delayedremove::DelayedRemove;
-use rand;
+use crate::gamerng::EffectRng;
 use rand::Rng;
 
 pub struct SpawnParticleSystem {
@@ -28,6 +28,7 @@ impl<'s> System<'s> for SpawnParticleSystem {
         WriteStorage<'s, DelayedRemove>,
         ReadExpect<'s, SpriteAnimationStore>,
         Entities<'s>,
+        Write<'s, EffectRng>,
     );
 
     fn run(&mut self, (
@@ -37,9 +38,9 @@ impl<'s> System<'s> for SpawnParticleSystem {
                 mut sprite_render, 
                 mut delayed_removes,
                 sprite_animation_store, 
-                entities): Self::SystemData) {
+                entities,
+                mut rng): Self::SystemData) {
         let delta = time.delta_seconds();
-        let mut rng = rand::prelude::thread_rng();
         let random_number: f32 = rng.gen();
         let probability_to_spawn = delta / self.average_part_spawn;
         if random_number < probability_to_spawn {
@@ -0,0 +1,120 @@
+//! Record the input of a run and play it back.
+//!
+//! A replay stores the dungeon, the seed of the `GameRng`, the
+//! `WorldGeometry` and the `PlayerInput` of every frame.  Recording and
+//! playback use the same fixed timestep, so every frame of the replay sees
+//! exactly the same state as during the recording.  Like map files,
+//! replays are stored as JSON if the file ends with `.json` and as RON
+//! otherwise.
+
+use crate::geometry::WorldGeometry;
+use crate::map::Map;
+use crate::mapfile::{self, MapFile, MapFileError};
+use crate::playerinput::{InputScript, InputStep, PlayerInput};
+use crate::room::Room;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Version of the replay format which is written by this build.
+pub const REPLAY_FORMAT_VERSION: u32 = 2;
+
+/// Top level structure of a replay file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    /// Duration of every frame in seconds.
+    pub timestep: f32,
+    pub geometry: WorldGeometry,
+    pub map: MapFile,
+    pub frames: Vec<PlayerInput>,
+}
+
+/// Everything which can go wrong while reading or writing a replay.
+#[derive(Debug)]
+pub enum ReplayError {
+    File(MapFileError),
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::File(error) => write!(f, "{}", error),
+            ReplayError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Unsupported replay format version {}, expected {}",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<MapFileError> for ReplayError {
+    fn from(error: MapFileError) -> Self {
+        ReplayError::File(error)
+    }
+}
+
+impl Replay {
+    /// Create a replay of the current version.
+    pub fn new(
+        seed: u64,
+        timestep: f32,
+        geometry: WorldGeometry,
+        map: &Map<Room>,
+        frames: Vec<PlayerInput>,
+    ) -> Replay {
+        Replay {
+            version: REPLAY_FORMAT_VERSION,
+            seed,
+            timestep,
+            geometry,
+            map: MapFile::from_map(map),
+            frames,
+        }
+    }
+
+    /// Input script which plays one recorded frame per timestep.
+    pub fn input_script(&self) -> InputScript {
+        InputScript {
+            steps: self
+                .frames
+                .iter()
+                .map(|input| InputStep {
+                    duration: self.timestep,
+                    move_x: input.move_x,
+                    move_y: input.move_y,
+                    attack: input.attack,
+                })
+                .collect(),
+        }
+    }
+
+    /// Split the replay into the dungeon and the input script.
+    pub fn into_parts(self) -> Result<(Map<Room>, InputScript), ReplayError> {
+        let script = self.input_script();
+        Ok((self.map.into_map()?, script))
+    }
+}
+
+/// Write the replay to the given path.
+pub fn save_replay(replay: &Replay, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+    mapfile::write_file(replay, path.as_ref())?;
+    Ok(())
+}
+
+/// Read a replay from the given path.
+pub fn load_replay(path: impl AsRef<Path>) -> Result<Replay, ReplayError> {
+    let replay: Replay = mapfile::read_file(path.as_ref())?;
+    if replay.version != REPLAY_FORMAT_VERSION {
+        return Err(ReplayError::UnsupportedVersion {
+            found: replay.version,
+            supported: REPLAY_FORMAT_VERSION,
+        });
+    }
+    Ok(replay)
+}
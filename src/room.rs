@@ -1,6 +1,97 @@
+//! Rooms of the dungeon and their generation.
+//!
+//! ## Coordinate conventions
+//! Rooms live on the map where y grows to the south, so the room north of
+//! (0, 0) is (0, -1).  Inside a room, fields use the pixel direction where
+//! y grows to the north, so the north border of a room is the row
+//! `height - 1`.  `ExitDirection` translates between both.
+
 use rand::Rng;
+use rand::distributions::{Distribution, Standard};
+use serde::{Deserialize, Serialize};
+
+/// Side of a room where an exit is placed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitDirection {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Distribution<ExitDirection> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> ExitDirection {
+        match rng.gen_range(0, 4) {
+            0 => ExitDirection::North,
+            1 => ExitDirection::South,
+            2 => ExitDirection::East,
+            3 => ExitDirection::West,
 
-#[derive(Copy, Clone)]
+            // Not possible but the compiler doesn't know that.
+            _ => ExitDirection::North
+        }
+    }
+}
+
+impl ExitDirection {
+    /// Offset of the neighbor room in map coordinates.
+    pub fn relative_pos(&self) -> (i32, i32) {
+        match self {
+            ExitDirection::North => (0, -1),
+            ExitDirection::South => (0, 1),
+            ExitDirection::East => (1, 0),
+            ExitDirection::West => (-1, 0),
+        }
+    }
+
+    /// Coordinate of the neighbor room in this direction.
+    pub fn add(&self, coordinates: (i32, i32)) -> (i32, i32) {
+        let relative_pos = self.relative_pos();
+        (relative_pos.0 + coordinates.0, relative_pos.1 + coordinates.1)
+    }
+
+    pub fn reverse(&self) -> ExitDirection {
+        match self {
+            ExitDirection::North => ExitDirection::South,
+            ExitDirection::South => ExitDirection::North,
+            ExitDirection::East => ExitDirection::West,
+            ExitDirection::West => ExitDirection::East,
+        }
+    }
+
+    /// Field of the exit in a room of the given size in room coordinates.
+    pub fn exit_field(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            ExitDirection::North => (width / 2, height - 1),
+            ExitDirection::South => (width / 2, 0),
+            ExitDirection::East => (width - 1, height / 2),
+            ExitDirection::West => (0, height / 2),
+        }
+    }
+
+    /// Exit direction of a field on the border of a room.
+    ///
+    /// Returns None for fields which are not on the border and for corners.
+    pub fn from_border_field(width: usize, height: usize, (x, y): (usize, usize)) -> Option<ExitDirection> {
+        let on_vertical_border = x == 0 || x == width - 1;
+        let on_horizontal_border = y == 0 || y == height - 1;
+        match (on_vertical_border, on_horizontal_border) {
+            (true, false) if x == 0 => Some(ExitDirection::West),
+            (true, false) => Some(ExitDirection::East),
+            (false, true) if y == 0 => Some(ExitDirection::South),
+            (false, true) => Some(ExitDirection::North),
+            _ => None,
+        }
+    }
+}
+
+/// Destination of an exit.
+///
+/// The first two values are the destination room, either relative to the
+/// room with the exit or as absolute map coordinate.  The last two values
+/// are the field of the paired exit in the destination room which leads
+/// back.  The player spawns next to that exit, see `Room::entry_point`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DestRoom {
     Relative(isize, isize, i32, i32,),
     Absolute(isize, isize, i32, i32,),
@@ -14,7 +105,8 @@ impl DestRoom {
         }
     }
 
-    pub fn spawn_point(&self) -> (i32, i32) {
+    /// Field of the paired exit in the destination room.
+    pub fn dest_exit(&self) -> (i32, i32) {
         match self {
             DestRoom::Relative(_, _, x, y) => (*x, *y),
             DestRoom::Absolute(_, _, x, y) => (*x, *y),
@@ -22,21 +114,55 @@ impl DestRoom {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum RoomField {
     Nothing,
     Wall,
     Stone,
     Bush,
     Player,
+    Enemy,
     Exit(DestRoom),
 }
 
-#[derive(Clone)]
+impl RoomField {
+    /// Fields which block the way and cannot be destroyed.
+    ///
+    /// Bushes are not solid here because the player can cut through them.
+    pub fn is_solid(&self) -> bool {
+        match self {
+            RoomField::Wall | RoomField::Stone => true,
+            _ => false,
+        }
+    }
+}
+
+/// Purpose of a room in the dungeon.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomRole {
+    Normal,
+    /// The room where the game starts.
+    Start,
+    /// The room farthest away from the start.
+    Boss,
+    /// Dead end with a reward.
+    Treasure,
+    Shop,
+}
+
+impl Default for RoomRole {
+    fn default() -> Self {
+        RoomRole::Normal
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Room {
     pub width: usize,
     pub height: usize,
     pub fields: Vec<RoomField>,
+    #[serde(default)]
+    pub role: RoomRole,
 }
 
 impl Room {
@@ -46,6 +172,7 @@ impl Room {
             width,
             height,
             fields,
+            role: RoomRole::Normal,
         }
     }
 
@@ -65,6 +192,92 @@ impl Room {
         }
     }
 
+    /// Positions of all exits in the room.
+    pub fn exits(&self) -> Vec<(usize, usize)> {
+        self.room_field_iterator()
+            .filter_map(|(x, y, field)| match field {
+                RoomField::Exit(_) => Some((x, y)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Field where a player arrives who enters the room through the given exit.
+    ///
+    /// This is the field next to the exit on the inner side of the room.
+    /// Returns None if the field is not on the border.
+    pub fn entry_point(&self, exit: (usize, usize)) -> Option<(usize, usize)> {
+        let (x, y) = exit;
+        ExitDirection::from_border_field(self.width, self.height, exit).map(|direction| {
+            match direction {
+                ExitDirection::West => (x + 1, y),
+                ExitDirection::East => (x - 1, y),
+                ExitDirection::South => (x, y + 1),
+                ExitDirection::North => (x, y - 1),
+            }
+        })
+    }
+
+    /// Entry points of all exits, see `entry_point`.
+    pub fn entry_points(&self) -> Vec<(usize, usize)> {
+        self.exits()
+            .into_iter()
+            .filter_map(|exit| self.entry_point(exit))
+            .collect()
+    }
+
+    /// Flood fill from the given field.
+    ///
+    /// Returns a flag for each field which is true if it can be reached
+    /// from `start` without passing a solid field.  The index is the same
+    /// as in `fields`.
+    pub fn reachable_fields(&self, (start_x, start_y): (usize, usize)) -> Vec<bool> {
+        let mut reachable = vec![false; self.fields.len()];
+        let mut stack = Vec::new();
+        if start_x < self.width && start_y < self.height {
+            stack.push((start_x, start_y));
+        }
+        while let Some((x, y)) = stack.pop() {
+            let index = x + y * self.width;
+            if reachable[index] || self.fields[index].is_solid() {
+                continue;
+            }
+            reachable[index] = true;
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < self.width {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < self.height {
+                stack.push((x, y + 1));
+            }
+        }
+        reachable
+    }
+
+    /// Check if all exits, entry points and the player can reach each other.
+    pub fn is_connected(&self) -> bool {
+        let mut required = self.exits();
+        required.append(&mut self.entry_points());
+        required.extend(
+            self.room_field_iterator()
+                .filter(|(_, _, field)| *field == RoomField::Player)
+                .map(|(x, y, _)| (x, y)),
+        );
+        if let Some(start) = required.first() {
+            let reachable = self.reachable_fields(*start);
+            required
+                .iter()
+                .all(|(x, y)| reachable[x + y * self.width])
+        } else {
+            true
+        }
+    }
+
     pub fn room_field_iterator(&self) -> RoomFieldIterator {
         RoomFieldIterator {
             x: 0,
@@ -83,12 +296,89 @@ pub struct RoomGeneration {
     pub exit_south: bool,
     pub exit_east: bool,
     pub exit_west: bool,
+
+    pub role: RoomRole,
 }
 
 
+/// Smallest width and height of a generated room.
+///
+/// Decorations keep two fields away from the borders, so smaller rooms
+/// have no inner fields left.
+pub const MIN_ROOM_SIZE: usize = 6;
+
+/// How often the room decoration is rolled again if it blocks the way.
+const MAX_DECORATION_ATTEMPTS: usize = 10;
+
+/// How often a random field is tried for each enemy.
+const MAX_ENEMY_PLACEMENT_ATTEMPTS: usize = 10;
+
+/// Minimum number of fields between the player and an enemy, so the player
+/// isn't hurt right at the start.
+const MIN_ENEMY_DISTANCE: isize = 5;
+
 impl RoomGeneration {
+    /// Open the exit in the given direction.
+    pub fn set_exit(&mut self, direction: ExitDirection) {
+        match direction {
+            ExitDirection::North => self.exit_north = true,
+            ExitDirection::South => self.exit_south = true,
+            ExitDirection::East => self.exit_east = true,
+            ExitDirection::West => self.exit_west = true,
+        }
+    }
+
+    /// Directions of all open exits.
+    pub fn exit_directions(&self) -> Vec<ExitDirection> {
+        let exits = [
+            (self.exit_north, ExitDirection::North),
+            (self.exit_south, ExitDirection::South),
+            (self.exit_east, ExitDirection::East),
+            (self.exit_west, ExitDirection::West),
+        ];
+        exits
+            .iter()
+            .filter(|(open, _)| *open)
+            .map(|(_, direction)| *direction)
+            .collect()
+    }
+
+    /// Destination of the exit in the given direction.
+    ///
+    /// The neighbor room is expected to have the same size, so the paired
+    /// exit is on the opposite side at the same position.
+    pub fn dest_room(&self, direction: ExitDirection) -> DestRoom {
+        let (rel_x, rel_y) = direction.relative_pos();
+        let (exit_x, exit_y) = direction.reverse().exit_field(self.width, self.height);
+        DestRoom::Relative(rel_x as isize, rel_y as isize, exit_x as i32, exit_y as i32)
+    }
+
     pub fn generate_room(&self, rng: &mut impl Rng) -> Room {
+        let base = self.generate_base_room();
+        let mut room = base.clone();
+        for _ in 0..MAX_DECORATION_ATTEMPTS {
+            room = base.clone();
+            self.decorate_room(&mut room, rng);
+            if room.is_connected() {
+                return room;
+            }
+        }
+
+        /* Still blocked, so remove the stones which are the only solid
+         * fields in the inner room. */
+        warn!("Couldn't generate a connected room, removing the stones");
+        for field in room.fields.iter_mut() {
+            if *field == RoomField::Stone {
+                *field = RoomField::Nothing;
+            }
+        }
+        room
+    }
+
+    /// Create the empty room with the borders and the exits.
+    fn generate_base_room(&self) -> Room {
         let mut room = Room::new(self.width, self.height);
+        room.role = self.role;
 
         /* Draw borders */
         let wall_borders = RoomField::Wall;
@@ -101,45 +391,99 @@ impl RoomGeneration {
             room.set_field(self.width - 1, y, wall_borders);
         }
 
-        /* Open exits */
-        if self.exit_north {
-            room.set_field(self.width / 2, self.height - 1,
-                RoomField::Exit(DestRoom::Relative(0, -1, self.width as i32 / 2, 1)));
-        }
-        if self.exit_south {
-            room.set_field(self.width / 2, 0,
-                RoomField::Exit(DestRoom::Relative(0, 1, self.width as i32 / 2, self.height as i32 - 2)));
+        self.open_exits(&mut room);
+        room
+    }
+
+    /// Place the exits on the borders of the room.
+    fn open_exits(&self, room: &mut Room) {
+        for direction in self.exit_directions() {
+            let (x, y) = direction.exit_field(self.width, self.height);
+            room.set_field(x, y, RoomField::Exit(self.dest_room(direction)));
         }
-        if self.exit_east {
-            room.set_field(self.width - 1, self.height / 2, 
-                RoomField::Exit(DestRoom::Relative(1, 0, 1, self.height as i32 / 2)));
+    }
+
+    /// Generate the room based on a prefab.
+    ///
+    /// If the prefab has no player field, the player is placed on a random
+    /// free field.  Returns None if the prefab blocks the way between the
+    /// exits.
+    pub fn generate_room_from_prefab(&self, prefab: &Room, rng: &mut impl Rng) -> Option<Room> {
+        let mut room = prefab.clone();
+        room.role = self.role;
+        self.open_exits(&mut room);
+        if !room.fields.contains(&RoomField::Player) {
+            let entry_points = room.entry_points();
+            let free_fields: Vec<(usize, usize)> = room
+                .room_field_iterator()
+                .filter(|(x, y, field)| *field == RoomField::Nothing && !entry_points.contains(&(*x, *y)))
+                .map(|(x, y, _)| (x, y))
+                .collect();
+            if free_fields.is_empty() {
+                return None;
+            }
+            let (x, y) = free_fields[rng.gen_range(0, free_fields.len())];
+            room.set_field(x, y, RoomField::Player);
         }
-        if self.exit_west {
-            room.set_field(0, self.height / 2,
-                RoomField::Exit(DestRoom::Relative(-1, 0, self.width as i32 - 2, self.height as i32 / 2)));
+        if room.is_connected() {
+            Some(room)
+        } else {
+            None
         }
+    }
 
+    /// Place stones, bushes, the player and enemies in the room.
+    ///
+    /// Everything is placed at least two fields away from the borders, so
+    /// the fields next to the exits stay free.  The amount depends on the
+    /// role: the start room has no stones and no enemies, boss rooms are
+    /// full of enemies, shops are empty and treasures are hidden between
+    /// many bushes.
+    fn decorate_room(&self, room: &mut Room, rng: &mut impl Rng) {
+        let (stones, bushes, enemies) = match self.role {
+            RoomRole::Normal => (rng.gen_range(5, 8), rng.gen_range(5, 8), rng.gen_range(1, 4)),
+            RoomRole::Start => (0, rng.gen_range(5, 8), 0),
+            RoomRole::Boss => (0, 0, rng.gen_range(4, 7)),
+            RoomRole::Shop => (0, 0, 0),
+            RoomRole::Treasure => (0, rng.gen_range(10, 15), rng.gen_range(0, 2)),
+        };
 
-        /* Draw 5-7 random stones */
-        for _ in 0..rng.gen_range(5, 8) {
-            let x = rng.gen_range(2, self.width - 3);
-            let y = rng.gen_range(2, self.height - 3);
+        for _ in 0..stones {
+            let (x, y) = self.random_inner_field(rng);
             room.set_field(x, y, RoomField::Stone);
         }
 
-        /* Draw 5-7 bushes */
-        for _ in 0..rng.gen_range(5, 8) {
-            let x = rng.gen_range(2, self.width - 3);
-            let y = rng.gen_range(2, self.height - 3);
+        for _ in 0..bushes {
+            let (x, y) = self.random_inner_field(rng);
             room.set_field(x, y, RoomField::Bush);
         }
 
         /* Add the player somewhere */
-        let x = rng.gen_range(2, self.width - 3);
-        let y = rng.gen_range(2, self.height - 3);
-        room.set_field(x, y, RoomField::Player);
+        let (player_x, player_y) = self.random_inner_field(rng);
+        room.set_field(player_x, player_y, RoomField::Player);
 
-        room
+        /* Enemies start on free fields away from the player, if there is
+         * no such field, there are less enemies. */
+        for _ in 0..enemies {
+            for _ in 0..MAX_ENEMY_PLACEMENT_ATTEMPTS {
+                let (x, y) = self.random_inner_field(rng);
+                let distance = (x as isize - player_x as isize).abs()
+                    + (y as isize - player_y as isize).abs();
+                if room.get_field(x, y) == Some(RoomField::Nothing)
+                    && distance >= MIN_ENEMY_DISTANCE
+                {
+                    room.set_field(x, y, RoomField::Enemy);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn random_inner_field(&self, rng: &mut impl Rng) -> (usize, usize) {
+        (
+            rng.gen_range(2, self.width - 3),
+            rng.gen_range(2, self.height - 3),
+        )
     }
 }
 
@@ -165,4 +509,62 @@ impl<'a> Iterator for RoomFieldIterator<'a> {
         }
         result
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const ROLES: [RoomRole; 5] = [
+        RoomRole::Normal,
+        RoomRole::Start,
+        RoomRole::Boss,
+        RoomRole::Treasure,
+        RoomRole::Shop,
+    ];
+
+    fn room_generation(width: usize, height: usize, role: RoomRole, exits: u8) -> RoomGeneration {
+        RoomGeneration {
+            width,
+            height,
+            exit_north: exits & 1 != 0,
+            exit_south: exits & 2 != 0,
+            exit_east: exits & 4 != 0,
+            exit_west: exits & 8 != 0,
+            role,
+            ..RoomGeneration::default()
+        }
+    }
+
+    #[test]
+    fn decorated_rooms_are_connected() {
+        for seed in 0..300 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let role = ROLES[seed as usize % ROLES.len()];
+            let exits = rng.gen_range(1, 16);
+            let room = room_generation(20, 15, role, exits).generate_room(&mut rng);
+            assert!(room.is_connected(), "seed {}", seed);
+
+            let (player_x, player_y, _) = room
+                .room_field_iterator()
+                .find(|(_, _, field)| *field == RoomField::Player)
+                .expect("room without player");
+            let reachable = room.reachable_fields((player_x, player_y));
+            for (x, y) in room.entry_points() {
+                assert!(reachable[x + y * room.width], "seed {}: entry point {:?} blocked", seed, (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn smallest_rooms_can_be_decorated() {
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let role = ROLES[seed as usize % ROLES.len()];
+            let room = room_generation(MIN_ROOM_SIZE, MIN_ROOM_SIZE, role, 15).generate_room(&mut rng);
+            assert_eq!(room.fields.len(), MIN_ROOM_SIZE * MIN_ROOM_SIZE);
+            assert!(room.is_connected(), "seed {}", seed);
+        }
+    }
+}
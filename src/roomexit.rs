@@ -7,6 +7,7 @@ use amethyst::ecs::SystemData;
 
 
 use specs_physics::events::{ProximityEvent, ProximityEvents};
+use crate::charactermove::UserMove;
 use crate::room::DestRoom;
 use crate::room;
 
@@ -14,8 +15,12 @@ impl Component for DestRoom {
     type Storage = VecStorage<Self>;
 }
 
-pub struct PerformRoomExit(pub room::DestRoom, pub (i32, i32));
+/// Request to leave the current room through an exit.
+///
+/// The spawn point in the destination room is resolved by the map.
+pub struct PerformRoomExit(pub room::DestRoom);
 
+/// Requests a room exit when the player touches an exit.
 pub struct RoomExitSystem {
     reader: ReaderId<ProximityEvent>
 }
@@ -43,27 +48,29 @@ impl<'s> System<'s> for RoomExitSystem {
         ReadStorage<'s, DestRoom>,
         Write<'s, Option<PerformRoomExit>>,
         ReadStorage<'s, Transform>,
+        ReadStorage<'s, UserMove>,
     );
 
     fn run(
         &mut self,
-        (channel, destrooms, mut perform_room_exit, transforms,): Self::SystemData,
+        (channel, destrooms, mut perform_room_exit, transforms, user_moves): Self::SystemData,
     ) {
         for collision in channel.read(&mut self.reader) {
-            let solid_entity = collision.collider1;
-            if let Some(exit) = destrooms.get(solid_entity) {
-                if let Some(_transform) = transforms.get(solid_entity) {
-                    let position = exit.spawn_point();
-                    *perform_room_exit = Some(PerformRoomExit(*exit, position));
+            // Only the player leaves the room, enemies and swords stay.
+            let pairs = [
+                (collision.collider1, collision.collider2),
+                (collision.collider2, collision.collider1),
+            ];
+            for (solid_entity, other_entity) in pairs.iter() {
+                if !user_moves.contains(*other_entity) {
+                    continue;
                 }
-            }
-            let solid_entity = collision.collider2;
-            if let Some(exit) = destrooms.get(solid_entity) {
-                if let Some(_transform) = transforms.get(solid_entity) {
-                    let position = exit.spawn_point();
-                    *perform_room_exit = Some(PerformRoomExit(*exit, position));
+                if let Some(exit) = destrooms.get(*solid_entity) {
+                    if let Some(_transform) = transforms.get(*solid_entity) {
+                        *perform_room_exit = Some(PerformRoomExit(*exit));
+                    }
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
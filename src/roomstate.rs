@@ -0,0 +1,102 @@
+//! Keep the changes in a room while the player is somewhere else.
+//!
+//! Entities which can change during the game get a `RoomTile` with the
+//! field they were created from.  When the player leaves the room, the
+//! remaining entities are compared with the room fields and the result is
+//! stored as `RoomState`.  When the room is entered again, the state is
+//! used to skip removed entities and to restore their health.
+
+use amethyst::{
+    ecs::{Component, DenseVecStorage, Join, ReadStorage},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::damage::Destroyable;
+use crate::health::Health;
+use crate::room::{Room, RoomField};
+
+/// Field of the room an entity was created from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoomTile(pub usize, pub usize);
+
+impl Component for RoomTile {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Changes in a room compared to the generated room.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomState {
+    /// Fields whose entity was destroyed, picked up or defeated.
+    pub removed: BTreeSet<(usize, usize)>,
+    /// Remaining health of the entities which are still there.
+    ///
+    /// Stored as a list because JSON only supports strings as map keys.
+    pub health: Vec<((usize, usize), f32)>,
+}
+
+impl RoomState {
+    pub fn is_removed(&self, tile: (usize, usize)) -> bool {
+        self.removed.contains(&tile)
+    }
+
+    /// Stored health of the field or the default if it was not damaged.
+    pub fn health(&self, tile: (usize, usize), default: f32) -> f32 {
+        self.health
+            .iter()
+            .find(|(health_tile, _)| *health_tile == tile)
+            .map_or(default, |(_, health)| *health)
+    }
+}
+
+/// Fields whose entities are tracked in the room state.
+pub fn is_tracked(field: RoomField) -> bool {
+    match field {
+        RoomField::Bush | RoomField::Enemy => true,
+        _ => false,
+    }
+}
+
+/// Read the state of the current room from the world.
+///
+/// Every tracked field of the room which has no entity anymore counts as
+/// removed.  Fields which were already removed stay removed.
+pub fn capture_room_state(world: &mut World, room: &Room, previous: &RoomState) -> RoomState {
+    // Make sure entities deleted in this frame are gone.
+    world.maintain();
+
+    let mut state = RoomState {
+        removed: previous.removed.clone(),
+        health: Vec::new(),
+    };
+    let (room_tiles, destroyables, healths): (
+        ReadStorage<RoomTile>,
+        ReadStorage<Destroyable>,
+        ReadStorage<Health>,
+    ) = world.system_data();
+
+    let mut alive = BTreeSet::new();
+    for (room_tile, destroyable, health) in
+        (&room_tiles, destroyables.maybe(), healths.maybe()).join()
+    {
+        // A dying enemy counts as removed.
+        if health.map_or(false, Health::is_dead) {
+            continue;
+        }
+        let tile = (room_tile.0, room_tile.1);
+        alive.insert(tile);
+        let health = destroyable
+            .map(|destroyable| destroyable.health)
+            .or_else(|| health.map(|health| health.health));
+        if let Some(health) = health {
+            state.health.push((tile, health));
+        }
+    }
+    for (x, y, field) in room.room_field_iterator() {
+        if is_tracked(field) && !alive.contains(&(x, y)) {
+            state.removed.insert((x, y));
+        }
+    }
+    state
+}
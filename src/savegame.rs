@@ -0,0 +1,186 @@
+//! Save and load the progress of a run.
+//!
+//! A save contains the whole dungeon, the changes in every visited room,
+//! the room the player is in and the position and health of the player.
+//! Like map files, saves are stored as JSON if the file ends with `.json`
+//! and as RON otherwise.
+
+use crate::map::{Coordinate, Map};
+use crate::mapfile::{self, MapFile, MapFileError};
+use crate::room::Room;
+use crate::roomstate::RoomState;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Version of the save format which is written by this build.
+pub const SAVE_FORMAT_VERSION: u32 = 2;
+
+/// Progress of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveGame {
+    pub map: Map<Room>,
+    pub room_states: Map<RoomState>,
+    pub room_coordinate: Coordinate,
+    /// Pixel position of the player in the current room.
+    ///
+    /// If it's None, the player starts at the player field of the room.
+    pub player_position: Option<(f32, f32)>,
+    pub player_health: f32,
+}
+
+/// Top level structure of a save file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub map: MapFile,
+    pub room_states: Vec<RoomStateEntry>,
+    pub room_coordinate: Coordinate,
+    pub player_position: Option<(f32, f32)>,
+    pub player_health: f32,
+}
+
+/// State of one visited room together with its coordinate.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomStateEntry {
+    pub coordinate: Coordinate,
+    pub state: RoomState,
+}
+
+/// Everything which can go wrong while reading or writing a save.
+#[derive(Debug)]
+pub enum SaveGameError {
+    File(MapFileError),
+    UnsupportedVersion { found: u32, supported: u32 },
+    MissingRoom(Coordinate),
+}
+
+impl fmt::Display for SaveGameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveGameError::File(error) => write!(f, "{}", error),
+            SaveGameError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Unsupported save format version {}, expected {}",
+                found, supported
+            ),
+            SaveGameError::MissingRoom(coordinate) => {
+                write!(f, "The saved room {:?} is not part of the map", coordinate)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveGameError {}
+
+impl From<MapFileError> for SaveGameError {
+    fn from(error: MapFileError) -> Self {
+        SaveGameError::File(error)
+    }
+}
+
+impl SaveFile {
+    /// Create a save file of the current version.
+    pub fn from_save_game(save_game: &SaveGame) -> SaveFile {
+        SaveFile {
+            version: SAVE_FORMAT_VERSION,
+            map: MapFile::from_map(&save_game.map),
+            room_states: save_game
+                .room_states
+                .iter()
+                .map(|(coordinate, state)| RoomStateEntry {
+                    coordinate,
+                    state: state.clone(),
+                })
+                .collect(),
+            room_coordinate: save_game.room_coordinate,
+            player_position: save_game.player_position,
+            player_health: save_game.player_health,
+        }
+    }
+
+    /// Verify the file and turn it into the progress of the run.
+    pub fn into_save_game(self) -> Result<SaveGame, SaveGameError> {
+        if self.version != SAVE_FORMAT_VERSION {
+            return Err(SaveGameError::UnsupportedVersion {
+                found: self.version,
+                supported: SAVE_FORMAT_VERSION,
+            });
+        }
+        let map = self.map.into_map()?;
+        if !map.has_room(self.room_coordinate) {
+            return Err(SaveGameError::MissingRoom(self.room_coordinate));
+        }
+        let mut room_states = Map::new();
+        for RoomStateEntry { coordinate, state } in self.room_states {
+            room_states.add_room(coordinate, state);
+        }
+        Ok(SaveGame {
+            map,
+            room_states,
+            room_coordinate: self.room_coordinate,
+            player_position: self.player_position,
+            player_health: self.player_health,
+        })
+    }
+}
+
+/// Write the progress to the given path.
+pub fn save_game(save_game: &SaveGame, path: impl AsRef<Path>) -> Result<(), SaveGameError> {
+    mapfile::write_file(&SaveFile::from_save_game(save_game), path.as_ref())?;
+    Ok(())
+}
+
+/// Read the progress from the given path.
+pub fn load_game(path: impl AsRef<Path>) -> Result<SaveGame, SaveGameError> {
+    let save_file: SaveFile = mapfile::read_file(path.as_ref())?;
+    save_file.into_save_game()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{generate_dungeon, DungeonGen};
+    use crate::prefab::PrefabLibrary;
+
+    fn example_save_game() -> SaveGame {
+        let layout = DungeonGen { corridor_length: 5, splits: 4 };
+        let map = generate_dungeon(7, &layout, &PrefabLibrary::default(), 20, 15);
+        let mut state = RoomState::default();
+        state.removed.insert((3, 4));
+        state.health.push(((5, 6), 1.5));
+        let mut room_states = Map::new();
+        room_states.add_room((0, 0), state);
+        SaveGame {
+            map,
+            room_states,
+            room_coordinate: (0, 0),
+            player_position: Some((120.5, 64.25)),
+            player_health: 2.5,
+        }
+    }
+
+    fn round_trip(extension: &str) {
+        let save_game = example_save_game();
+        let path = std::env::temp_dir().join(format!(
+            "sprite_game_save_{}.{}",
+            std::process::id(),
+            extension
+        ));
+        mapfile::write_file(&SaveFile::from_save_game(&save_game), &path).unwrap();
+        let save_file: Result<SaveFile, _> = mapfile::read_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = save_file.unwrap().into_save_game().unwrap();
+        assert_eq!(save_game, loaded);
+    }
+
+    #[test]
+    fn ron_save_round_trips() {
+        round_trip("ron");
+    }
+
+    #[test]
+    fn json_save_round_trips() {
+        round_trip("json");
+    }
+}
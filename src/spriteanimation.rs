@@ -1,24 +1,89 @@
 //! Support to handle animations for sprites.
 
 use amethyst::{
-    core::timing::Time,
-    ecs::prelude::{Join, Read, System, WriteStorage},
+    core::{shrev::EventChannel, timing::Time},
+    ecs::prelude::{Entities, Entity, Join, Read, System, Write, WriteStorage},
     ecs::{Component, DenseVecStorage},
     renderer::SpriteRender,
 };
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::spriteanimationloader::AnimationDefinition;
+
+/// Seconds a frame is shown if neither the animation nor the sprite
+/// defines a duration.
+pub const DEFAULT_FRAME_DURATION: f32 = 0.1;
+
+/// Shortest frame duration, so a zero duration can't stall the system.
+const MIN_FRAME_DURATION: f32 = 0.001;
+
+/// How an animation continues after its last frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlaybackMode {
+    /// Start again with the first frame.
+    Loop,
+    /// Stop and keep showing the last frame.
+    Once,
+    /// Stop and delete the entity, for effects like sword swings.
+    OnceAndRemove,
+    /// Play backwards to the first frame and forwards again.
+    PingPong,
+    /// Loop from the last frame to the first one.
+    Reverse,
+}
+
+impl Default for PlaybackMode {
+    fn default() -> Self {
+        PlaybackMode::Loop
+    }
+}
+
+/// Published when an animation which plays once reached its end.
+///
+/// For `OnceAndRemove` the entity is already deleted when the event is
+/// read.
+#[derive(Clone, Debug)]
+pub struct AnimationFinishedEvent {
+    pub entity: Entity,
+}
+
+/// Published when a frame with event tags is shown, once per tag.
+///
+/// Systems read them like the `ProximityEvents` of the physics, for
+/// example to activate a hitbox on the swing frame.
+#[derive(Clone, Debug)]
+pub struct AnimationFrameEvent {
+    pub entity: Entity,
+    /// Event tag from the animation definition.
+    pub name: String,
+    /// Position of the frame in the animation.
+    pub frame: usize,
+}
 
 /// Component which holds a sprite animation
 ///
 /// This includes the sprite indices for the animation, the
-/// animation speed, if the animation should pause and other
+/// duration of each frame, if the animation should pause and other
 /// information required to draw a sprite animation.
 #[derive(Default)]
 pub struct SpriteAnimation {
     pub index: u32,
     pub keys: Vec<usize>,
+    /// Seconds each key is shown, keys without a duration use `speed`.
+    pub durations: Vec<f32>,
     pub speed: f32,
     pub time: f32,
     pub pause: bool,
+    pub mode: PlaybackMode,
+    /// Direction of a ping-pong animation.
+    pub forward: bool,
+    /// Set once an animation which plays once reached its end.
+    pub finished: bool,
+    /// Event tags by the position of the frame in `keys`.
+    pub events: BTreeMap<usize, Vec<String>>,
+    /// Set once the events of the first frame are published.
+    pub started: bool,
 }
 
 impl SpriteAnimation {
@@ -26,11 +91,109 @@ impl SpriteAnimation {
         SpriteAnimation {
             index: 0,
             keys,
+            durations: Vec::new(),
             speed,
             time: 0.0,
             pause: false,
+            mode: PlaybackMode::Loop,
+            forward: true,
+            finished: false,
+            events: BTreeMap::new(),
+            started: false,
         }
     }
+
+    /// Animation with the frames, durations and mode of a definition.
+    pub fn from_definition(definition: &AnimationDefinition) -> Self {
+        let mut sprite_animation = SpriteAnimation::new(Vec::new(), DEFAULT_FRAME_DURATION);
+        sprite_animation.play(definition);
+        sprite_animation
+    }
+
+    /// Switch to another animation and start it from the beginning.
+    pub fn play(&mut self, definition: &AnimationDefinition) {
+        self.keys = definition.keys.clone();
+        self.durations = definition.durations.clone();
+        self.mode = definition.mode;
+        self.events = definition.events.clone();
+        self.restart();
+    }
+
+    /// Switch to another animation but continue at the current frame.
+    ///
+    /// Used when a character turns around, so a clip which plays once
+    /// keeps its progress and doesn't start again.
+    pub fn switch(&mut self, definition: &AnimationDefinition) {
+        self.keys = definition.keys.clone();
+        self.durations = definition.durations.clone();
+        self.mode = definition.mode;
+        self.events = definition.events.clone();
+        self.index = self.index.min(self.keys.len().saturating_sub(1) as u32);
+    }
+
+    /// Start the current animation from the beginning.
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+        self.finished = false;
+        self.started = false;
+        self.forward = self.mode != PlaybackMode::Reverse;
+        self.index = if self.forward {
+            0
+        } else {
+            self.keys.len().saturating_sub(1) as u32
+        };
+    }
+
+    /// Sprite index of the current frame.
+    pub fn current_key(&self) -> Option<usize> {
+        self.keys.get(self.index as usize).copied()
+    }
+
+    /// Event tags of the current frame.
+    pub fn current_events(&self) -> &[String] {
+        self.events
+            .get(&(self.index as usize))
+            .map_or(&[], |events| events.as_slice())
+    }
+
+    /// Seconds the current frame is shown.
+    pub fn frame_duration(&self) -> f32 {
+        self.durations
+            .get(self.index as usize)
+            .copied()
+            .unwrap_or(self.speed)
+            .max(MIN_FRAME_DURATION)
+    }
+
+    /// Move to the next frame, returns false if the animation ended.
+    fn advance(&mut self) -> bool {
+        let last = self.keys.len().saturating_sub(1) as u32;
+        match self.mode {
+            PlaybackMode::Loop => {
+                self.index = if self.index >= last { 0 } else { self.index + 1 };
+            }
+            PlaybackMode::Reverse => {
+                self.index = if self.index == 0 { last } else { self.index - 1 };
+            }
+            PlaybackMode::Once | PlaybackMode::OnceAndRemove => {
+                if self.index >= last {
+                    return false;
+                }
+                self.index += 1;
+            }
+            PlaybackMode::PingPong => {
+                if self.forward && self.index >= last || !self.forward && self.index == 0 {
+                    self.forward = !self.forward;
+                }
+                self.index = if self.forward {
+                    (self.index + 1).min(last)
+                } else {
+                    self.index.saturating_sub(1)
+                };
+            }
+        }
+        true
+    }
 }
 
 impl Component for SpriteAnimation {
@@ -38,29 +201,271 @@ impl Component for SpriteAnimation {
 }
 
 /// System to handle sprite animation.
+///
+/// Publishes the `AnimationFrameEvent`s of every frame which is shown,
+/// even if a long time step skips it, and an `AnimationFinishedEvent`
+/// when an animation which plays once ends.
 pub struct SpriteAnimationSystem;
 
 impl<'s> System<'s> for SpriteAnimationSystem {
     type SystemData = (
+        Entities<'s>,
         WriteStorage<'s, SpriteRender>,
         WriteStorage<'s, SpriteAnimation>,
         Read<'s, Time>,
+        Write<'s, EventChannel<AnimationFrameEvent>>,
+        Write<'s, EventChannel<AnimationFinishedEvent>>,
     );
 
-    fn run(&mut self, (mut sprite_renders, mut sprite_animations, time): Self::SystemData) {
-        for (mut sprite_render, mut sprite_animation) in
-            (&mut sprite_renders, &mut sprite_animations).join()
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut sprite_renders,
+            mut sprite_animations,
+            time,
+            mut frame_events,
+            mut finished_events,
+        ): Self::SystemData,
+    ) {
+        for (entity, mut sprite_render, mut sprite_animation) in
+            (&entities, &mut sprite_renders, &mut sprite_animations).join()
         {
-            if !sprite_animation.pause {
-                sprite_animation.time += time.delta_seconds();
-                while sprite_animation.time > sprite_animation.speed {
-                    sprite_animation.index =
-                        (sprite_animation.index + 1) % (sprite_animation.keys.len() as u32);
-                    sprite_render.sprite_number =
-                        sprite_animation.keys[sprite_animation.index as usize];
-                    sprite_animation.time -= sprite_animation.speed;
+            if sprite_animation.pause || sprite_animation.finished || sprite_animation.keys.is_empty() {
+                continue;
+            }
+            if !sprite_animation.started {
+                sprite_animation.started = true;
+                publish_frame_events(&mut frame_events, entity, &sprite_animation);
+            }
+            sprite_animation.time += time.delta_seconds();
+            while sprite_animation.time > sprite_animation.frame_duration() {
+                sprite_animation.time -= sprite_animation.frame_duration();
+                if !sprite_animation.advance() {
+                    sprite_animation.finished = true;
+                    break;
+                }
+                publish_frame_events(&mut frame_events, entity, &sprite_animation);
+            }
+            if let Some(key) = sprite_animation.current_key() {
+                sprite_render.sprite_number = key;
+            }
+            if sprite_animation.finished {
+                finished_events.single_write(AnimationFinishedEvent { entity });
+                if sprite_animation.mode == PlaybackMode::OnceAndRemove {
+                    if let Err(error) = entities.delete(entity) {
+                        warn!("Removing animation {} failed: {}", entity.id(), error);
+                    }
                 }
             }
         }
     }
 }
+
+fn publish_frame_events(
+    channel: &mut EventChannel<AnimationFrameEvent>,
+    entity: Entity,
+    sprite_animation: &SpriteAnimation,
+) {
+    for name in sprite_animation.current_events() {
+        channel.single_write(AnimationFrameEvent {
+            entity,
+            name: name.clone(),
+            frame: sprite_animation.index as usize,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::{
+        assets::AssetStorage,
+        core::shrev::ReaderId,
+        ecs::{Builder, RunNow, System, World, WorldExt},
+        renderer::SpriteSheet,
+    };
+
+    fn animation(keys: Vec<usize>, mode: PlaybackMode) -> SpriteAnimation {
+        SpriteAnimation::from_definition(&AnimationDefinition {
+            keys,
+            mode,
+            ..AnimationDefinition::default()
+        })
+    }
+
+    /// Frame positions after advancing the given number of times.
+    fn advance_times(sprite_animation: &mut SpriteAnimation, times: usize) -> Vec<u32> {
+        (0..times)
+            .map(|_| {
+                sprite_animation.advance();
+                sprite_animation.index
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_starts_again() {
+        let mut sprite_animation = animation(vec![4, 5, 6], PlaybackMode::Loop);
+        assert_eq!(sprite_animation.current_key(), Some(4));
+        assert_eq!(advance_times(&mut sprite_animation, 4), vec![1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn once_holds_the_last_frame() {
+        let mut sprite_animation = animation(vec![4, 5, 6], PlaybackMode::Once);
+        assert!(sprite_animation.advance());
+        assert!(sprite_animation.advance());
+        assert!(!sprite_animation.advance());
+        assert_eq!(sprite_animation.current_key(), Some(6));
+    }
+
+    #[test]
+    fn ping_pong_turns_around() {
+        let mut sprite_animation = animation(vec![4, 5, 6], PlaybackMode::PingPong);
+        assert_eq!(advance_times(&mut sprite_animation, 6), vec![1, 2, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn reverse_starts_at_the_last_frame() {
+        let mut sprite_animation = animation(vec![4, 5, 6], PlaybackMode::Reverse);
+        assert_eq!(sprite_animation.current_key(), Some(6));
+        assert_eq!(advance_times(&mut sprite_animation, 3), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn frames_have_their_own_duration() {
+        let mut sprite_animation = SpriteAnimation::from_definition(&AnimationDefinition {
+            keys: vec![4, 5, 6],
+            durations: vec![0.5, 0.0],
+            ..AnimationDefinition::default()
+        });
+        assert_eq!(sprite_animation.frame_duration(), 0.5);
+        sprite_animation.advance();
+        assert_eq!(sprite_animation.frame_duration(), MIN_FRAME_DURATION);
+        sprite_animation.advance();
+        assert_eq!(sprite_animation.frame_duration(), DEFAULT_FRAME_DURATION);
+    }
+
+    /// World with the system set up and readers for both event channels.
+    fn setup(
+        delta: f32,
+    ) -> (
+        World,
+        SpriteAnimationSystem,
+        ReaderId<AnimationFrameEvent>,
+        ReaderId<AnimationFinishedEvent>,
+    ) {
+        let mut world = World::new();
+        let mut system = SpriteAnimationSystem;
+        System::setup(&mut system, &mut world);
+        world.write_resource::<Time>().set_delta_seconds(delta);
+        let frame_reader = world
+            .write_resource::<EventChannel<AnimationFrameEvent>>()
+            .register_reader();
+        let finished_reader = world
+            .write_resource::<EventChannel<AnimationFinishedEvent>>()
+            .register_reader();
+        (world, system, frame_reader, finished_reader)
+    }
+
+    /// Animation entity where every frame is shown half a second.
+    fn create_animation(world: &mut World, keys: Vec<usize>, mode: PlaybackMode) -> Entity {
+        let durations = vec![0.5; keys.len()];
+        let sprite_animation = SpriteAnimation::from_definition(&AnimationDefinition {
+            keys,
+            durations,
+            mode,
+            ..AnimationDefinition::default()
+        });
+        let sprite_render = SpriteRender {
+            sprite_sheet: AssetStorage::<SpriteSheet>::default().allocate(),
+            sprite_number: 0,
+        };
+        world.create_entity().with(sprite_render).with(sprite_animation).build()
+    }
+
+    fn sprite_number(world: &World, entity: Entity) -> usize {
+        world.read_storage::<SpriteRender>().get(entity).unwrap().sprite_number
+    }
+
+    #[test]
+    fn system_shows_the_frames_of_the_time_step() {
+        let (mut world, mut system, _, _) = setup(0.75);
+        let entity = create_animation(&mut world, vec![10, 11, 12], PlaybackMode::Loop);
+        let mut shown = Vec::new();
+        for _ in 0..3 {
+            system.run_now(&world);
+            shown.push(sprite_number(&world, entity));
+        }
+        assert_eq!(shown, vec![11, 12, 11]);
+    }
+
+    #[test]
+    fn finished_once_animation_stays() {
+        let (mut world, mut system, _, mut finished_reader) = setup(0.75);
+        let entity = create_animation(&mut world, vec![10, 11], PlaybackMode::Once);
+        system.run_now(&world);
+        let finished = world
+            .read_resource::<EventChannel<AnimationFinishedEvent>>()
+            .read(&mut finished_reader)
+            .count();
+        assert_eq!(finished, 0);
+
+        system.run_now(&world);
+        world.maintain();
+        let finished: Vec<_> = world
+            .read_resource::<EventChannel<AnimationFinishedEvent>>()
+            .read(&mut finished_reader)
+            .map(|event| event.entity)
+            .collect();
+        assert_eq!(finished, vec![entity]);
+        assert!(world.is_alive(entity));
+        assert_eq!(sprite_number(&world, entity), 11);
+    }
+
+    #[test]
+    fn once_and_remove_deletes_the_entity() {
+        let (mut world, mut system, _, mut finished_reader) = setup(0.75);
+        let entity = create_animation(&mut world, vec![10, 11], PlaybackMode::OnceAndRemove);
+        system.run_now(&world);
+        system.run_now(&world);
+        world.maintain();
+        let finished: Vec<_> = world
+            .read_resource::<EventChannel<AnimationFinishedEvent>>()
+            .read(&mut finished_reader)
+            .map(|event| event.entity)
+            .collect();
+        assert_eq!(finished, vec![entity]);
+        assert!(!world.is_alive(entity));
+    }
+
+    #[test]
+    fn skipped_frames_publish_their_events() {
+        let (mut world, mut system, mut frame_reader, _) = setup(1.75);
+        let entity = create_animation(&mut world, vec![10, 11, 12, 13], PlaybackMode::Loop);
+        {
+            let mut sprite_animations = world.write_storage::<SpriteAnimation>();
+            let events = &mut sprite_animations.get_mut(entity).unwrap().events;
+            events.insert(0, vec!["start".to_string()]);
+            events.insert(1, vec!["swing".to_string()]);
+            events.insert(2, vec!["hit".to_string(), "sound".to_string()]);
+        }
+        system.run_now(&world);
+        let published: Vec<_> = world
+            .read_resource::<EventChannel<AnimationFrameEvent>>()
+            .read(&mut frame_reader)
+            .map(|event| (event.frame, event.name.clone()))
+            .collect();
+        assert_eq!(
+            published,
+            vec![
+                (0, "start".to_string()),
+                (1, "swing".to_string()),
+                (2, "hit".to_string()),
+                (2, "sound".to_string()),
+            ]
+        );
+        assert_eq!(sprite_number(&world, entity), 13);
+    }
+}
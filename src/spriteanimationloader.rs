@@ -1,8 +1,15 @@
-//! Load animations and sprites from a ron file.
+//! Load animations and sprites from ron files.
+//!
+//! The sprites are split into atlases, for example characters, tiles and
+//! effects.  A manifest lists the atlases, they are loaded once into the
+//! `SpriteAnimationStore` which resolves names over all of them.
+//!
+//! Atlases are RON files of our TexturePacker exporter or JSON files
+//! which are read by `spriteimport`.
 
 use amethyst::{
     assets::{AssetStorage, Loader},
-    config::Config,
+    config::{Config, ConfigError},
     prelude::*,
     renderer::{ImageFormat, Sprite, SpriteSheet, /*SpriteSheetHandle,*/ Texture, /*TextureMetadata*/
         sprite::SpriteSheetHandle, SpriteRender,
@@ -11,6 +18,14 @@ use amethyst::{
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+
+use crate::spriteanimation::{PlaybackMode, DEFAULT_FRAME_DURATION};
+use crate::spriteimport::{self, SpriteImportError};
+
+/// Offset of the sprites which don't define one, in pixels.
+pub const DEFAULT_SPRITE_OFFSET: (f32, f32) = (0.5, 0.5);
 
 /// Definition of one sprite in the RON file.
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +36,29 @@ pub struct SpriteDefinition {
     pub width: u32,
     pub height: u32,
     pub offset: Option<(f32, f32)>,
+    /// Seconds the sprite is shown in an animation, if the atlas defines it.
+    #[serde(default)]
+    pub duration: Option<f32>,
+}
+
+/// Frames and playback of one animation.
+///
+/// In the RON file only the playback has to be given for animations
+/// which are built from the sprite names, for example
+/// `"hero_attack_up": (mode: Once, durations: [0.05, 0.05, 0.2], events: {1: ["hit"]})`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationDefinition {
+    /// Sprite indices, taken from the sprite names if empty.
+    pub keys: Vec<usize>,
+    /// Seconds each frame is shown.  After loading there is one entry per
+    /// key, missing ones use the duration of the sprite or
+    /// `DEFAULT_FRAME_DURATION`.
+    pub durations: Vec<f32>,
+    pub mode: PlaybackMode,
+    /// Event tags which are published when a frame is shown, by the
+    /// position of the frame in `keys`.
+    pub events: BTreeMap<usize, Vec<String>>,
 }
 
 /// RON file definition.
@@ -30,7 +68,7 @@ pub struct AnimationData {
     pub texture_width: u32,
     pub texture_height: u32,
     pub sprites: Vec<SpriteDefinition>,
-    pub animations: BTreeMap<String, Vec<usize>>,
+    pub animations: BTreeMap<String, AnimationDefinition>,
     pub images: BTreeMap<String, usize>,
 }
 
@@ -47,22 +85,139 @@ impl Default for AnimationData {
     }
 }
 
+/// Sprites and animations of one sprite sheet.
+#[derive(Clone)]
+pub struct SpriteAtlas {
+    pub name: String,
+    pub sprite_sheet_handle: SpriteSheetHandle,
+    pub animations: BTreeMap<String, AnimationDefinition>,
+    pub images: BTreeMap<String, usize>,
+}
+
+/// List of atlases in the RON manifest.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AtlasManifest {
+    pub atlases: Vec<AtlasEntry>,
+}
+
+/// One atlas in the manifest.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AtlasEntry {
+    pub name: String,
+    /// RON file of the atlas, relative to the manifest.
+    pub file: String,
+}
+
+/// Everything which can go wrong while building the store.
+#[derive(Debug)]
+pub enum SpriteStoreError {
+    Load { path: String, error: ConfigError },
+    Import(SpriteImportError),
+    DuplicateAtlas(String),
+    DuplicateImage { name: String, first_atlas: String, second_atlas: String },
+    DuplicateAnimation { name: String, first_atlas: String, second_atlas: String },
+}
+
+impl fmt::Display for SpriteStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpriteStoreError::Load { path, error } => {
+                write!(f, "Couldn't load {}: {}", path, error)
+            }
+            SpriteStoreError::Import(error) => write!(f, "{}", error),
+            SpriteStoreError::DuplicateAtlas(name) => {
+                write!(f, "There are two sprite atlases named {}", name)
+            }
+            SpriteStoreError::DuplicateImage { name, first_atlas, second_atlas } => write!(
+                f,
+                "Sprite {} is defined in atlas {} and in atlas {}",
+                name, first_atlas, second_atlas
+            ),
+            SpriteStoreError::DuplicateAnimation { name, first_atlas, second_atlas } => write!(
+                f,
+                "Animation {} is defined in atlas {} and in atlas {}",
+                name, first_atlas, second_atlas
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpriteStoreError {}
+
+impl From<SpriteImportError> for SpriteStoreError {
+    fn from(error: SpriteImportError) -> Self {
+        SpriteStoreError::Import(error)
+    }
+}
+
 /// Stores all animations and sprites and sprites which can be used
 /// ingame.
+///
+/// The sprites can come from several atlases, every name is resolved
+/// over all of them.
+#[derive(Clone, Default)]
 pub struct SpriteAnimationStore {
-    pub sprite_sheet_handle: SpriteSheetHandle,
-    pub animations: BTreeMap<String, Vec<usize>>,
-    pub images: BTreeMap<String, usize>,
+    pub atlases: Vec<SpriteAtlas>,
+    /// Index of the atlas which contains an image.
+    image_atlases: BTreeMap<String, usize>,
+    /// Index of the atlas which contains an animation.
+    animation_atlases: BTreeMap<String, usize>,
 }
 
 impl SpriteAnimationStore {
+    /// Add an atlas, its names must not be used by another atlas.
+    pub fn add_atlas(&mut self, atlas: SpriteAtlas) -> Result<(), SpriteStoreError> {
+        if self.atlas(&atlas.name).is_some() {
+            return Err(SpriteStoreError::DuplicateAtlas(atlas.name));
+        }
+        for name in atlas.images.keys() {
+            if let Some(index) = self.image_atlases.get(name) {
+                return Err(SpriteStoreError::DuplicateImage {
+                    name: name.clone(),
+                    first_atlas: self.atlases[*index].name.clone(),
+                    second_atlas: atlas.name,
+                });
+            }
+        }
+        for name in atlas.animations.keys() {
+            if let Some(index) = self.animation_atlases.get(name) {
+                return Err(SpriteStoreError::DuplicateAnimation {
+                    name: name.clone(),
+                    first_atlas: self.atlases[*index].name.clone(),
+                    second_atlas: atlas.name,
+                });
+            }
+        }
+        let index = self.atlases.len();
+        for name in atlas.images.keys() {
+            self.image_atlases.insert(name.clone(), index);
+        }
+        for name in atlas.animations.keys() {
+            self.animation_atlases.insert(name.clone(), index);
+        }
+        self.atlases.push(atlas);
+        Ok(())
+    }
+
+    pub fn atlas(&self, name: &str) -> Option<&SpriteAtlas> {
+        self.atlases.iter().find(|atlas| atlas.name == name)
+    }
+
     pub fn get_sprite_render(&self, name: &str) -> Option<SpriteRender> {
-        self.images.get(name)
+        let atlas = &self.atlases[*self.image_atlases.get(name)?];
+        atlas.images.get(name)
             .map(|index| SpriteRender {
-                sprite_sheet: self.sprite_sheet_handle.clone(),
+                sprite_sheet: atlas.sprite_sheet_handle.clone(),
                 sprite_number: *index
             })
     }
+
+    /// Sprite sheet and definition of an animation.
+    pub fn get_animation(&self, name: &str) -> Option<(&SpriteSheetHandle, &AnimationDefinition)> {
+        let atlas = &self.atlases[*self.animation_atlases.get(name)?];
+        atlas.animations.get(name)
+            .map(|animation| (&atlas.sprite_sheet_handle, animation))
+    }
 }
 
 /// Use an AnimationData and create animations and sprite images based on sprite names.
@@ -70,45 +225,78 @@ impl SpriteAnimationStore {
 /// If a name ends with underscores followed by numbers it is treated as part of an animations.
 /// In this case it will add it to the animations, otherwise it will simply store the index in
 /// the images tree map under that name.
+///
+/// Animations which are already in the AnimationData, like the ones of the RON file, keep their
+/// playback mode and durations.  If they also list keys, these replace the ones from the names.
+/// Afterwards every animation has a duration for each key.
 pub fn manually_assign_animations(animation_data: &mut AnimationData) {
-    let mut animations: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut animations: BTreeMap<String, AnimationDefinition> = BTreeMap::new();
     let mut images: BTreeMap<String, usize> = BTreeMap::new();
 
     let ends_with_number_pattern = Regex::new(r"_\d+$").unwrap();
     for (i, sprite) in (0..).zip(&animation_data.sprites) {
         if let Some(_) = ends_with_number_pattern.find(&sprite.name) {
             let animation_name = ends_with_number_pattern.replace_all(&sprite.name, "");
-            println!("Animation name: {}", animation_name);
+            debug!("Animation name: {}", animation_name);
             let entry = animations
                 .entry(animation_name.to_string())
-                .or_insert_with(|| Vec::new());
-            entry.push(i);
+                .or_insert_with(AnimationDefinition::default);
+            entry.keys.push(i);
         } else {
             images.insert(sprite.name.to_string(), i);
         }
     }
 
+    for (name, definition) in mem::replace(&mut animation_data.animations, BTreeMap::new()) {
+        let entry = animations.entry(name.clone()).or_insert_with(AnimationDefinition::default);
+        if !definition.keys.is_empty() {
+            entry.keys = definition.keys;
+        }
+        entry.durations = definition.durations;
+        entry.mode = definition.mode;
+        entry.events = definition.events;
+        if entry.keys.is_empty() {
+            warn!("Animation {} has no sprites", name);
+            animations.remove(&name);
+        }
+    }
+    for animation in animations.values_mut() {
+        let sprites = &animation_data.sprites;
+        let durations = animation.keys.iter().enumerate().map(|(i, key)| {
+            animation.durations.get(i).copied()
+                .or_else(|| sprites.get(*key).and_then(|sprite| sprite.duration))
+                .unwrap_or(DEFAULT_FRAME_DURATION)
+        }).collect();
+        animation.durations = durations;
+    }
+
     animation_data.animations = animations;
     animation_data.images = images;
 }
 
-/// Load animations and images from the given ron file.
+/// Load animations and images from the given ron or json file.
 ///
 /// It requires a mutable reference to the world, a directory, where the assets are stored and the filename
 /// of the ron file inside the directory.  The reference to the image file in the ron file is relative to the
-/// directory provides as second argument.
-pub fn load_sprites(
+/// directory provides as second argument.  Files ending with `.json` are imported as TexturePacker or
+/// Aseprite JSON.
+pub fn load_atlas(
     world: &mut World,
-    directory: impl ToString,
-    filename: impl ToString,
-) -> SpriteAnimationStore {
+    directory: &str,
+    name: &str,
+    filename: &str,
+) -> Result<SpriteAtlas, SpriteStoreError> {
     // ---- Loading animations
-    info!("Loading animations");
-    let directory = directory.to_string();
-    let filename = filename.to_string();
-    let ron_path = format!("{}/{}", directory, filename);
-    let mut animations = AnimationData::load(ron_path).expect("Animation data should load");
-    manually_assign_animations(&mut animations);
+    info!("Loading animations of atlas {}", name);
+    let atlas_path = format!("{}/{}", directory, filename);
+    let animations = if filename.ends_with(".json") {
+        spriteimport::load_json_atlas(&atlas_path)?
+    } else {
+        let mut animations = AnimationData::load(&atlas_path)
+            .map_err(|error| SpriteStoreError::Load { path: atlas_path.clone(), error })?;
+        manually_assign_animations(&mut animations);
+        animations
+    };
     let texture_path = format!("{}/{}", directory, animations.texture_path);
     let texture_handle = {
         let loader = world.read_resource::<Loader>();
@@ -122,11 +310,8 @@ pub fn load_sprites(
     };
     let mut sprites = Vec::with_capacity(animations.sprites.len());
     for sprite in animations.sprites {
-        let offset = if let Some((offset_x, offset_y)) = sprite.offset {
-            [offset_x, offset_y]
-        } else {
-            [0.5; 2]
-        };
+        let (offset_x, offset_y) = sprite.offset.unwrap_or(DEFAULT_SPRITE_OFFSET);
+        let offset = [offset_x, offset_y];
         sprites.push(Sprite::from_pixel_values(
             animations.texture_width,
             animations.texture_height,
@@ -153,9 +338,103 @@ pub fn load_sprites(
         )
     };
 
-    SpriteAnimationStore {
+    Ok(SpriteAtlas {
+        name: name.to_string(),
         sprite_sheet_handle,
-        animations: animations.animations.clone(),
-        images: animations.images.clone(),
+        animations: animations.animations,
+        images: animations.images,
+    })
+}
+
+/// Load all atlases listed in the manifest in the given directory.
+pub fn load_sprite_store(
+    world: &mut World,
+    directory: &str,
+    manifest: &str,
+) -> Result<SpriteAnimationStore, SpriteStoreError> {
+    let manifest_path = format!("{}/{}", directory, manifest);
+    let manifest = AtlasManifest::load(&manifest_path)
+        .map_err(|error| SpriteStoreError::Load { path: manifest_path, error })?;
+    let mut store = SpriteAnimationStore::default();
+    for entry in manifest.atlases {
+        let atlas = load_atlas(world, directory, &entry.name, &entry.file)?;
+        store.add_atlas(atlas)?;
+    }
+    Ok(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Atlas with the given names, all of them point to the first sprite.
+    fn atlas(name: &str, images: &[&str], animations: &[&str]) -> SpriteAtlas {
+        SpriteAtlas {
+            name: name.to_string(),
+            sprite_sheet_handle: AssetStorage::<SpriteSheet>::default().allocate(),
+            animations: animations
+                .iter()
+                .map(|name| (name.to_string(), AnimationDefinition::default()))
+                .collect(),
+            images: images.iter().map(|name| (name.to_string(), 0)).collect(),
+        }
+    }
+
+    fn store() -> SpriteAnimationStore {
+        let mut store = SpriteAnimationStore::default();
+        store.add_atlas(atlas("characters", &["healer"], &["healer_walk"])).unwrap();
+        store
+    }
+
+    #[test]
+    fn names_are_resolved_over_all_atlases() {
+        let mut store = store();
+        store.add_atlas(atlas("tiles", &["wall", "bush"], &["water"])).unwrap();
+        assert!(store.get_sprite_render("healer").is_some());
+        assert!(store.get_sprite_render("bush").is_some());
+        assert!(store.get_animation("healer_walk").is_some());
+        assert!(store.get_animation("water").is_some());
+        assert!(store.get_sprite_render("water").is_none());
+    }
+
+    #[test]
+    fn duplicate_atlas_is_rejected() {
+        let mut store = store();
+        match store.add_atlas(atlas("characters", &["slime"], &[])) {
+            Err(SpriteStoreError::DuplicateAtlas(name)) => assert_eq!(name, "characters"),
+            result => panic!("Expected a duplicate atlas, got {:?}", result),
+        }
+        assert_eq!(store.atlases.len(), 1);
+    }
+
+    #[test]
+    fn duplicate_image_is_rejected() {
+        let mut store = store();
+        match store.add_atlas(atlas("tiles", &["wall", "healer"], &["water"])) {
+            Err(SpriteStoreError::DuplicateImage { name, first_atlas, second_atlas }) => {
+                assert_eq!(name, "healer");
+                assert_eq!(first_atlas, "characters");
+                assert_eq!(second_atlas, "tiles");
+            }
+            result => panic!("Expected a duplicate image, got {:?}", result),
+        }
+        assert_eq!(store.atlases.len(), 1);
+        assert!(store.get_sprite_render("wall").is_none());
+        assert!(store.get_animation("water").is_none());
+    }
+
+    #[test]
+    fn duplicate_animation_is_rejected() {
+        let mut store = store();
+        match store.add_atlas(atlas("tiles", &["wall"], &["healer_walk"])) {
+            Err(SpriteStoreError::DuplicateAnimation { name, first_atlas, second_atlas }) => {
+                assert_eq!(name, "healer_walk");
+                assert_eq!(first_atlas, "characters");
+                assert_eq!(second_atlas, "tiles");
+            }
+            result => panic!("Expected a duplicate animation, got {:?}", result),
+        }
+        assert_eq!(store.atlases.len(), 1);
+        assert!(store.get_sprite_render("wall").is_none());
     }
 }
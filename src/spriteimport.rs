@@ -0,0 +1,460 @@
+//! Import sprite atlases from the JSON formats of common tools.
+//!
+//! Supported are the JSON hash and JSON array exports of TexturePacker
+//! and the JSON export of Aseprite.  They all share the same structure:
+//! a `frames` list or map with one rectangle per sprite and a `meta`
+//! entry with the image.  Aseprite adds the duration of every frame and
+//! `frameTags`, which become animations with the playback direction of
+//! the tag.
+//!
+//! Sprite names are the file names of the frames without extension, so
+//! names ending with `_<number>` form animations like in the RON format.
+//!
+//! Trimmed frames keep their position in the untrimmed image, their
+//! offset moves them to where the removed border would have put them.
+
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::spriteanimation::PlaybackMode;
+use crate::spriteanimationloader::{
+    manually_assign_animations, AnimationData, AnimationDefinition, SpriteDefinition,
+    DEFAULT_SPRITE_OFFSET,
+};
+
+/// Everything which can go wrong while importing a JSON atlas.
+#[derive(Debug)]
+pub enum SpriteImportError {
+    Io { file: String, error: io::Error },
+    Json { file: String, error: serde_json::Error },
+    Frame { file: String, frame: String, error: serde_json::Error },
+    RotatedFrame { file: String, frame: String },
+    TrimmedFrame { file: String, frame: String },
+    Tag { file: String, tag: String, frames: usize },
+}
+
+impl fmt::Display for SpriteImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpriteImportError::Io { file, error } => write!(f, "Couldn't read {}: {}", file, error),
+            SpriteImportError::Json { file, error } => write!(f, "Invalid atlas {}: {}", file, error),
+            SpriteImportError::Frame { file, frame, error } => {
+                write!(f, "Invalid frame {} in {}: {}", frame, file, error)
+            }
+            SpriteImportError::RotatedFrame { file, frame } => write!(
+                f,
+                "Frame {} in {} is rotated, disable rotation in the export",
+                frame, file
+            ),
+            SpriteImportError::TrimmedFrame { file, frame } => write!(
+                f,
+                "Frame {} in {} is trimmed but has no spriteSourceSize and sourceSize",
+                frame, file
+            ),
+            SpriteImportError::Tag { file, tag, frames } => write!(
+                f,
+                "Tag {} in {} refers to frames beyond the {} frames of the atlas",
+                tag, file, frames
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpriteImportError {}
+
+/// Top level structure of all supported formats.
+#[derive(Deserialize)]
+struct JsonAtlas {
+    frames: JsonFrames,
+    meta: JsonMeta,
+}
+
+/// Frames of a JSON hash or JSON array export.
+///
+/// The frames are kept as raw values, so errors can name the frame.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonFrames {
+    Array(Vec<Value>),
+    Hash(OrderedMap),
+}
+
+/// JSON object which keeps the order of its entries.
+///
+/// Aseprite tags refer to the frames by their position.
+struct OrderedMap(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for OrderedMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OrderedMapVisitor;
+
+        impl<'de> Visitor<'de> for OrderedMapVisitor {
+            type Value = OrderedMap;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a map of frames")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<OrderedMap, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = access.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(OrderedMap(entries))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedMapVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+struct JsonFrame {
+    frame: JsonRect,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(default)]
+    trimmed: bool,
+    /// Rectangle of the trimmed frame in the untrimmed image.
+    #[serde(rename = "spriteSourceSize")]
+    sprite_source_size: Option<JsonRect>,
+    /// Size of the untrimmed image.
+    #[serde(rename = "sourceSize")]
+    source_size: Option<JsonSize>,
+    /// Milliseconds, only exported by Aseprite.
+    duration: Option<u32>,
+}
+
+/// Entry of a JSON array export, it contains its own name.
+#[derive(Deserialize)]
+struct JsonArrayFrame {
+    filename: String,
+}
+
+#[derive(Deserialize)]
+struct JsonRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonSize {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct JsonMeta {
+    image: String,
+    size: JsonSize,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<JsonTag>,
+}
+
+#[derive(Deserialize)]
+struct JsonTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String,
+}
+
+/// Read a TexturePacker or Aseprite JSON file.
+///
+/// Animations are assigned by the sprite names like for RON files,
+/// Aseprite tags are added as animations on top and replace animations
+/// with the same name.
+pub fn load_json_atlas(path: impl AsRef<Path>) -> Result<AnimationData, SpriteImportError> {
+    let file = path.as_ref().display().to_string();
+    let content = fs::read_to_string(path.as_ref()).map_err(|error| SpriteImportError::Io {
+        file: file.clone(),
+        error,
+    })?;
+    parse_json_atlas(&content, &file)
+}
+
+/// Parse the content of a JSON atlas, `file` is only used for errors.
+pub fn parse_json_atlas(content: &str, file: &str) -> Result<AnimationData, SpriteImportError> {
+    let atlas: JsonAtlas = serde_json::from_str(content).map_err(|error| SpriteImportError::Json {
+        file: file.to_string(),
+        error,
+    })?;
+
+    let named_frames = match atlas.frames {
+        JsonFrames::Hash(OrderedMap(entries)) => entries,
+        JsonFrames::Array(values) => {
+            let mut entries = Vec::with_capacity(values.len());
+            for (index, value) in values.into_iter().enumerate() {
+                let JsonArrayFrame { filename } = serde_json::from_value(value.clone())
+                    .map_err(|error| SpriteImportError::Frame {
+                        file: file.to_string(),
+                        frame: format!("#{}", index),
+                        error,
+                    })?;
+                entries.push((filename, value));
+            }
+            entries
+        }
+    };
+
+    let mut sprites = Vec::with_capacity(named_frames.len());
+    for (filename, value) in named_frames {
+        let frame: JsonFrame = serde_json::from_value(value).map_err(|error| SpriteImportError::Frame {
+            file: file.to_string(),
+            frame: filename.clone(),
+            error,
+        })?;
+        if frame.rotated {
+            return Err(SpriteImportError::RotatedFrame {
+                file: file.to_string(),
+                frame: filename,
+            });
+        }
+        let offset = if frame.trimmed {
+            match (&frame.sprite_source_size, &frame.source_size) {
+                (Some(trimmed), Some(source)) => Some(trimmed_offset(trimmed, source)),
+                _ => {
+                    return Err(SpriteImportError::TrimmedFrame {
+                        file: file.to_string(),
+                        frame: filename,
+                    })
+                }
+            }
+        } else {
+            None
+        };
+        sprites.push(SpriteDefinition {
+            name: sprite_name(&filename).to_string(),
+            x: frame.frame.x,
+            y: frame.frame.y,
+            width: frame.frame.w,
+            height: frame.frame.h,
+            offset,
+            duration: frame.duration.map(|duration| duration as f32 / 1000.0),
+        });
+    }
+
+    let mut animation_data = AnimationData {
+        texture_path: atlas.meta.image,
+        texture_width: atlas.meta.size.w,
+        texture_height: atlas.meta.size.h,
+        sprites,
+        ..AnimationData::default()
+    };
+
+    for tag in atlas.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= animation_data.sprites.len() {
+            return Err(SpriteImportError::Tag {
+                file: file.to_string(),
+                tag: tag.name,
+                frames: animation_data.sprites.len(),
+            });
+        }
+        let mode = match tag.direction.as_str() {
+            "reverse" => PlaybackMode::Reverse,
+            "pingpong" => PlaybackMode::PingPong,
+            _ => PlaybackMode::Loop,
+        };
+        let animation = AnimationDefinition {
+            keys: (tag.from..=tag.to).collect(),
+            mode,
+            ..AnimationDefinition::default()
+        };
+        animation_data.animations.insert(tag.name, animation);
+    }
+    manually_assign_animations(&mut animation_data);
+    Ok(animation_data)
+}
+
+/// Offset which puts a trimmed frame at its place in the untrimmed image.
+///
+/// Sprites are drawn centered on the entity and moved left and down by
+/// their offset.  The y axis of the image points down.
+fn trimmed_offset(trimmed: &JsonRect, source: &JsonSize) -> (f32, f32) {
+    let center_x = trimmed.x as f32 + trimmed.w as f32 / 2.0;
+    let center_y = trimmed.y as f32 + trimmed.h as f32 / 2.0;
+    (
+        DEFAULT_SPRITE_OFFSET.0 + source.w as f32 / 2.0 - center_x,
+        DEFAULT_SPRITE_OFFSET.1 + center_y - source.h as f32 / 2.0,
+    )
+}
+
+/// File name of a frame without directories and extension.
+fn sprite_name(filename: &str) -> &str {
+    let name = filename.rsplit('/').next().unwrap_or(filename);
+    match name.rfind('.') {
+        Some(index) if index > 0 => &name[..index],
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = r#"{
+        "frames": {
+            "hero_walk_0.png": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 } },
+            "hero_walk_1.png": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 } },
+            "tree.png": { "frame": { "x": 32, "y": 0, "w": 16, "h": 32 } }
+        },
+        "meta": { "image": "sprites.png", "size": { "w": 64, "h": 32 } }
+    }"#;
+
+    const ARRAY: &str = r#"{
+        "frames": [
+            { "filename": "effects/spark_0.png", "frame": { "x": 0, "y": 0, "w": 8, "h": 8 } },
+            { "filename": "effects/spark_1.png", "frame": { "x": 8, "y": 0, "w": 8, "h": 8 } }
+        ],
+        "meta": { "image": "effects.png", "size": { "w": 16, "h": 8 } }
+    }"#;
+
+    const ASEPRITE: &str = r#"{
+        "frames": {
+            "slime 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 },
+            "slime 1.aseprite": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 }, "duration": 200 },
+            "slime 2.aseprite": { "frame": { "x": 32, "y": 0, "w": 16, "h": 16 }, "duration": 300 }
+        },
+        "meta": {
+            "image": "slime.png",
+            "size": { "w": 48, "h": 16 },
+            "frameTags": [
+                { "name": "slime_idle", "from": 0, "to": 1, "direction": "pingpong" },
+                { "name": "slime_melt", "from": 1, "to": 2, "direction": "reverse" }
+            ]
+        }
+    }"#;
+
+    fn error_message(content: &str) -> String {
+        match parse_json_atlas(content, "atlas.json") {
+            Ok(_) => panic!("The atlas should be rejected"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn hash_export() {
+        let data = parse_json_atlas(HASH, "atlas.json").unwrap();
+        assert_eq!(data.texture_path, "sprites.png");
+        assert_eq!((data.texture_width, data.texture_height), (64, 32));
+        let names: Vec<_> = data.sprites.iter().map(|sprite| sprite.name.as_str()).collect();
+        assert_eq!(names, vec!["hero_walk_0", "hero_walk_1", "tree"]);
+        let tree = &data.sprites[2];
+        assert_eq!((tree.x, tree.y, tree.width, tree.height), (32, 0, 16, 32));
+        assert_eq!(tree.offset, None);
+        assert_eq!(data.animations["hero_walk"].keys, vec![0, 1]);
+        assert_eq!(data.images["tree"], 2);
+    }
+
+    #[test]
+    fn array_export() {
+        let data = parse_json_atlas(ARRAY, "atlas.json").unwrap();
+        assert_eq!(data.texture_path, "effects.png");
+        let names: Vec<_> = data.sprites.iter().map(|sprite| sprite.name.as_str()).collect();
+        assert_eq!(names, vec!["spark_0", "spark_1"]);
+        assert_eq!(data.sprites[1].x, 8);
+        assert_eq!(data.animations["spark"].keys, vec![0, 1]);
+    }
+
+    #[test]
+    fn aseprite_export() {
+        let data = parse_json_atlas(ASEPRITE, "atlas.json").unwrap();
+        assert_eq!(data.sprites[2].duration, Some(0.3));
+        let idle = &data.animations["slime_idle"];
+        assert_eq!(idle.keys, vec![0, 1]);
+        assert_eq!(idle.durations, vec![0.1, 0.2]);
+        assert_eq!(idle.mode, PlaybackMode::PingPong);
+        let melt = &data.animations["slime_melt"];
+        assert_eq!(melt.keys, vec![1, 2]);
+        assert_eq!(melt.mode, PlaybackMode::Reverse);
+    }
+
+    #[test]
+    fn trimmed_frames_keep_their_position() {
+        let data = parse_json_atlas(
+            r#"{
+                "frames": {
+                    "coin.png": {
+                        "frame": { "x": 0, "y": 0, "w": 8, "h": 12 },
+                        "trimmed": true,
+                        "spriteSourceSize": { "x": 2, "y": 0, "w": 8, "h": 12 },
+                        "sourceSize": { "w": 16, "h": 16 }
+                    }
+                },
+                "meta": { "image": "coin.png", "size": { "w": 8, "h": 12 } }
+            }"#,
+            "atlas.json",
+        )
+        .unwrap();
+        // The frame is 2 pixels left of and 2 pixels above the center of
+        // the untrimmed image.
+        assert_eq!(
+            data.sprites[0].offset,
+            Some((DEFAULT_SPRITE_OFFSET.0 + 2.0, DEFAULT_SPRITE_OFFSET.1 - 2.0))
+        );
+    }
+
+    #[test]
+    fn errors_name_file_and_frame() {
+        let message = error_message(
+            r#"{
+                "frames": { "hero.png": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "rotated": true } },
+                "meta": { "image": "sprites.png", "size": { "w": 16, "h": 16 } }
+            }"#,
+        );
+        assert_eq!(message, "Frame hero.png in atlas.json is rotated, disable rotation in the export");
+
+        let message = error_message(
+            r#"{
+                "frames": { "hero.png": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "trimmed": true } },
+                "meta": { "image": "sprites.png", "size": { "w": 16, "h": 16 } }
+            }"#,
+        );
+        assert_eq!(
+            message,
+            "Frame hero.png in atlas.json is trimmed but has no spriteSourceSize and sourceSize"
+        );
+
+        let message = error_message(
+            r#"{
+                "frames": { "hero.png": { "frame": { "x": 0, "y": 0 } } },
+                "meta": { "image": "sprites.png", "size": { "w": 16, "h": 16 } }
+            }"#,
+        );
+        assert!(message.starts_with("Invalid frame hero.png in atlas.json: "), "{}", message);
+
+        let message = error_message(
+            r#"{
+                "frames": [ { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 } } ],
+                "meta": { "image": "sprites.png", "size": { "w": 16, "h": 16 } }
+            }"#,
+        );
+        assert!(message.starts_with("Invalid frame #0 in atlas.json: "), "{}", message);
+
+        let message = error_message(
+            r#"{
+                "frames": { "hero 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 } } },
+                "meta": {
+                    "image": "sprites.png",
+                    "size": { "w": 16, "h": 16 },
+                    "frameTags": [ { "name": "hero_walk", "from": 0, "to": 3 } ]
+                }
+            }"#,
+        );
+        assert_eq!(
+            message,
+            "Tag hero_walk in atlas.json refers to frames beyond the 1 frames of the atlas"
+        );
+
+        let message = error_message(r#"{ "frames": {} }"#);
+        assert!(message.starts_with("Invalid atlas atlas.json: "), "{}", message);
+    }
+}
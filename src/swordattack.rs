@@ -1,5 +1,13 @@
+//! Performs a sword attack
+//!
+//! The sword plays its swing animation once and only hurts on the frames
+//! which are tagged with `hit`.
+
 use crate::{
-    charactermeta::CharacterDirection, damage::Destroyer, delayedremove::DelayedRemove,
+    charactermeta::CharacterDirection, damage::Destroyer,
+    geometry::WorldGeometry, health::Friendly,
+    spriteanimation::{AnimationFrameEvent, PlaybackMode, SpriteAnimation},
+    spriteanimationloader::{AnimationDefinition, SpriteAnimationStore},
 };
 use specs_physics::{PhysicsBodyBuilder, PhysicsBody,
     nphysics::object::BodyStatus,
@@ -8,7 +16,62 @@ use specs_physics::{PhysicsBodyBuilder, PhysicsBody,
     PhysicsCollider,
     colliders::Shape,
 };
-use amethyst::{core::Transform, ecs::world::World, prelude::*, renderer::SpriteRender};
+use amethyst::{
+    core::{shrev::{EventChannel, ReaderId}, Transform},
+    ecs::{world::World, Component, DenseVecStorage, Entities, Read, ReadStorage, System, Write, WriteStorage},
+    prelude::*,
+    renderer::SpriteRender,
+};
+
+/// Event tag of the frames on which the sword hits.
+pub const HIT_EVENT: &str = "hit";
+
+/// Seconds the sword is shown if the atlas has no swing animation.
+const SWING_DURATION: f32 = 0.1;
+
+/// Sword which hits once its animation reaches a `hit` frame.
+pub struct SwordSwing {
+    pub damage: f32,
+}
+impl Component for SwordSwing {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Sprite and animation of a sword swing.
+///
+/// Uses the `sword-attack-<direction>` animation of the atlases if there
+/// is one and otherwise shows the image with that name for a moment.
+/// The swing always plays once, without `hit` tags it hits on the first
+/// frame.
+pub fn sword_swing(
+    store: &SpriteAnimationStore,
+    direction: &CharacterDirection,
+) -> Option<(SpriteRender, AnimationDefinition)> {
+    let name = format!("sword-attack-{}", direction.as_str());
+    let (sprite, mut animation) = match store.get_animation(&name) {
+        Some((sprite_sheet, animation)) if !animation.keys.is_empty() => (
+            SpriteRender {
+                sprite_sheet: sprite_sheet.clone(),
+                sprite_number: animation.keys[0],
+            },
+            animation.clone(),
+        ),
+        _ => {
+            let sprite = store.get_sprite_render(&name)?;
+            let animation = AnimationDefinition {
+                keys: vec![sprite.sprite_number],
+                durations: vec![SWING_DURATION],
+                ..AnimationDefinition::default()
+            };
+            (sprite, animation)
+        }
+    };
+    animation.mode = PlaybackMode::OnceAndRemove;
+    if !animation.events.values().flatten().any(|event| event == HIT_EVENT) {
+        animation.events.entry(0).or_default().push(HIT_EVENT.to_string());
+    }
+    Some((sprite, animation))
+}
 
 pub fn sword_attack(
     world: &mut World,
@@ -16,32 +79,95 @@ pub fn sword_attack(
     transform: Transform,
     direciton: CharacterDirection,
     sprite: SpriteRender,
+    animation: AnimationDefinition,
 ) {
+    let geometry = *world.read_resource::<WorldGeometry>();
     let translation = transform.translation();
     let (x, y) = match direciton {
-        CharacterDirection::Up => (translation.x, translation.y + 32.0),
-        CharacterDirection::Down => (translation.x, translation.y -32.0),
-        CharacterDirection::Left => (translation.x -32.0, translation.y),
-        CharacterDirection::Right => (translation.x + 32.0, translation.y),
+        CharacterDirection::Up => (translation.x, translation.y + geometry.tile_size),
+        CharacterDirection::Down => (translation.x, translation.y - geometry.tile_size),
+        CharacterDirection::Left => (translation.x - geometry.tile_size, translation.y),
+        CharacterDirection::Right => (translation.x + geometry.tile_size, translation.y),
     };
     let mut damage_transform = Transform::default();
     damage_transform.set_translation_xyz(x, y, -y);
-        let physics_body: PhysicsBody<f32> = PhysicsBodyBuilder::from(BodyStatus::Dynamic)
-        .build();
-    let physics_collider: PhysicsCollider<f32> =
-        PhysicsColliderBuilder::from(Shape::Cuboid {
-            half_extents: Vector3::new(16.0, 16.0, 300.0)
-        })
-        .sensor(true)
-        .build();
 
     world
         .create_entity()
         .with(damage_transform)
-        .with(DelayedRemove::new(0.1))
-        .with(Destroyer { damage: strength })
-        .with(physics_body)
-        .with(physics_collider)
+        .with(SpriteAnimation::from_definition(&animation))
+        .with(SwordSwing { damage: strength })
+        .with(Friendly)
         .with(sprite)
         .build();
 }
+
+/// Gives a sword its hitbox when the animation shows a `hit` frame.
+///
+/// The collider is only added then, so the `ProximityEvents` which hurt
+/// are created on that frame.
+#[derive(Default)]
+pub struct SwordHitSystem {
+    reader: Option<ReaderId<AnimationFrameEvent>>,
+}
+
+impl<'s> System<'s> for SwordHitSystem {
+    type SystemData = (
+        Entities<'s>,
+        Write<'s, EventChannel<AnimationFrameEvent>>,
+        Read<'s, WorldGeometry>,
+        ReadStorage<'s, SwordSwing>,
+        WriteStorage<'s, Destroyer>,
+        WriteStorage<'s, PhysicsBody<f32>>,
+        WriteStorage<'s, PhysicsCollider<f32>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut channel,
+            geometry,
+            sword_swings,
+            mut destroyers,
+            mut physics_bodies,
+            mut physics_colliders,
+        ): Self::SystemData,
+    ) {
+        if let None = self.reader {
+            self.reader = Some(channel.register_reader());
+        }
+        let reader = self.reader.as_mut().unwrap();
+        for event in channel.read(reader) {
+            if event.name != HIT_EVENT || !entities.is_alive(event.entity) {
+                continue;
+            }
+            let sword_swing = match sword_swings.get(event.entity) {
+                Some(sword_swing) => sword_swing,
+                None => continue,
+            };
+            if destroyers.contains(event.entity) {
+                continue;
+            }
+            let physics_body: PhysicsBody<f32> = PhysicsBodyBuilder::from(BodyStatus::Dynamic)
+                .build();
+            let physics_collider: PhysicsCollider<f32> =
+                PhysicsColliderBuilder::from(Shape::Cuboid {
+                    half_extents: Vector3::new(
+                        geometry.tile_half_extent(),
+                        geometry.tile_half_extent(),
+                        300.0,
+                    )
+                })
+                .sensor(true)
+                .build();
+            let inserted = destroyers
+                .insert(event.entity, Destroyer { damage: sword_swing.damage })
+                .and_then(|_| physics_bodies.insert(event.entity, physics_body))
+                .and_then(|_| physics_colliders.insert(event.entity, physics_collider));
+            if let Err(error) = inserted {
+                warn!("Couldn't activate sword {}: {}", event.entity.id(), error);
+            }
+        }
+    }
+}
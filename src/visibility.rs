@@ -0,0 +1,186 @@
+//! Line of sight on the tile grid of a room.
+//!
+//! Walls, stones and bushes are opaque.  `line_of_sight` walks along a
+//! Bresenham line between two tiles, `field_of_view` casts such a line to
+//! every tile around an origin.  Both take the opacity as a closure, so
+//! they also work with the current state of a room where some bushes are
+//! already cut; `Room` wrappers use the generated fields.
+
+use crate::room::{Room, RoomField};
+
+/// Fields which can't be looked through.
+pub fn is_opaque_field(field: &RoomField) -> bool {
+    match field {
+        RoomField::Wall | RoomField::Stone | RoomField::Bush => true,
+        _ => false,
+    }
+}
+
+/// Tiles on the Bresenham line from `from` to `to`, both included.
+pub fn line((x0, y0): (usize, usize), (x1, y1): (usize, usize)) -> Vec<(usize, usize)> {
+    let (x0, y0, x1, y1) = (x0 as i64, y0 as i64, x1 as i64, y1 as i64);
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let step_x = if x0 < x1 { 1 } else { -1 };
+    let step_y = if y0 < y1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    let mut tiles = Vec::with_capacity((dx - dy) as usize + 1);
+    loop {
+        tiles.push((x as usize, y as usize));
+        if x == x1 && y == y1 {
+            return tiles;
+        }
+        let error2 = 2 * error;
+        if error2 >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if error2 <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Check if `to` can be seen from `from`.
+///
+/// Only the tiles in between must be transparent, so walls can be seen
+/// and a character standing in a bush can still look out.
+pub fn line_of_sight_with(
+    is_opaque: impl Fn((usize, usize)) -> bool,
+    from: (usize, usize),
+    to: (usize, usize),
+) -> bool {
+    let tiles = line(from, to);
+    tiles
+        .iter()
+        .skip(1)
+        .take(tiles.len().saturating_sub(2))
+        .all(|tile| !is_opaque(*tile))
+}
+
+/// Line of sight with the fields of the room.
+pub fn line_of_sight(room: &Room, from: (usize, usize), to: (usize, usize)) -> bool {
+    line_of_sight_with(|tile| room_is_opaque(room, tile), from, to)
+}
+
+/// Tiles which can be seen from `origin` within `radius` tiles.
+///
+/// Returns a flag for each tile of a `width` × `height` grid with the same
+/// index as `Room::fields`.
+pub fn field_of_view_with(
+    is_opaque: impl Fn((usize, usize)) -> bool,
+    width: usize,
+    height: usize,
+    origin: (usize, usize),
+    radius: usize,
+) -> Vec<bool> {
+    let mut visible = vec![false; width * height];
+    if origin.0 >= width || origin.1 >= height {
+        return visible;
+    }
+    let radius_squared = (radius * radius) as i64;
+    let min_x = origin.0.saturating_sub(radius);
+    let min_y = origin.1.saturating_sub(radius);
+    let max_x = (origin.0 + radius).min(width - 1);
+    let max_y = (origin.1 + radius).min(height - 1);
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let (dx, dy) = (x as i64 - origin.0 as i64, y as i64 - origin.1 as i64);
+            if dx * dx + dy * dy <= radius_squared && line_of_sight_with(&is_opaque, origin, (x, y)) {
+                visible[x + y * width] = true;
+            }
+        }
+    }
+    visible
+}
+
+/// Field of view with the fields of the room.
+pub fn field_of_view(room: &Room, origin: (usize, usize), radius: usize) -> Vec<bool> {
+    field_of_view_with(
+        |tile| room_is_opaque(room, tile),
+        room.width,
+        room.height,
+        origin,
+        radius,
+    )
+}
+
+fn room_is_opaque(room: &Room, (x, y): (usize, usize)) -> bool {
+    room.get_field(x, y).map_or(true, |field| is_opaque_field(&field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty room with walls around it.
+    fn walled_room() -> Room {
+        let mut room = Room::new(9, 9);
+        for i in 0..9 {
+            room.set_field(i, 0, RoomField::Wall);
+            room.set_field(i, 8, RoomField::Wall);
+            room.set_field(0, i, RoomField::Wall);
+            room.set_field(8, i, RoomField::Wall);
+        }
+        room
+    }
+
+    #[test]
+    fn straight_line() {
+        assert_eq!(line((1, 2), (4, 2)), vec![(1, 2), (2, 2), (3, 2), (4, 2)]);
+        assert_eq!(line((3, 4), (3, 1)), vec![(3, 4), (3, 3), (3, 2), (3, 1)]);
+        assert_eq!(line((2, 2), (2, 2)), vec![(2, 2)]);
+    }
+
+    #[test]
+    fn diagonal_line() {
+        assert_eq!(line((1, 1), (4, 4)), vec![(1, 1), (2, 2), (3, 3), (4, 4)]);
+        assert_eq!(line((4, 1), (1, 4)), vec![(4, 1), (3, 2), (2, 3), (1, 4)]);
+    }
+
+    #[test]
+    fn stone_blocks_the_sight() {
+        let mut room = walled_room();
+        assert!(line_of_sight(&room, (1, 4), (7, 4)));
+        room.set_field(4, 4, RoomField::Stone);
+        assert!(!line_of_sight(&room, (1, 4), (7, 4)));
+        assert!(!line_of_sight(&room, (7, 4), (1, 4)));
+        assert!(line_of_sight(&room, (1, 4), (7, 5)));
+    }
+
+    #[test]
+    fn walls_can_be_seen() {
+        let mut room = walled_room();
+        assert!(line_of_sight(&room, (4, 4), (8, 4)));
+        room.set_field(4, 4, RoomField::Bush);
+        assert!(line_of_sight(&room, (4, 4), (4, 1)));
+        assert!(!line_of_sight(&room, (4, 1), (4, 7)));
+    }
+
+    #[test]
+    fn field_of_view_ends_at_the_radius() {
+        let room = walled_room();
+        let visible = field_of_view(&room, (4, 4), 2);
+        let is_visible = |(x, y): (usize, usize)| visible[x + y * room.width];
+        assert!(is_visible((4, 4)));
+        assert!(is_visible((6, 4)));
+        assert!(is_visible((5, 5)));
+        assert!(!is_visible((7, 4)));
+        assert!(!is_visible((6, 6)));
+        assert!(!is_visible((1, 1)));
+    }
+
+    #[test]
+    fn field_of_view_stops_at_walls() {
+        let mut room = walled_room();
+        room.set_field(5, 4, RoomField::Stone);
+        let visible = field_of_view(&room, (3, 4), 8);
+        let is_visible = |(x, y): (usize, usize)| visible[x + y * room.width];
+        assert!(is_visible((5, 4)));
+        assert!(!is_visible((6, 4)));
+        assert!(is_visible((0, 4)));
+        assert!(is_visible((7, 1)));
+    }
+}
@@ -0,0 +1,136 @@
+//! Gameplay tests which run the game without a window.
+
+extern crate amethyst;
+extern crate sprite_game;
+
+use amethyst::config::Config;
+use sprite_game::{
+    game::GameSession,
+    geometry::WorldGeometry,
+    headless::HeadlessGame,
+    map::{self, DungeonGen},
+    playerinput::{InputScript, InputStep},
+    prefab::PrefabLibrary,
+    room::{ExitDirection, RoomField},
+    roomstate::RoomState,
+};
+use std::path::Path;
+
+const SEED: u64 = 42;
+const TIMESTEP: f32 = 1.0 / 60.0;
+
+fn app_root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// New run in the dungeon of the fixed seed.
+fn start_session() -> GameSession {
+    let geometry = WorldGeometry::default();
+    let layout = DungeonGen { corridor_length: 5, splits: 4 };
+    let map = map::generate_dungeon(
+        SEED,
+        &layout,
+        &PrefabLibrary::default(),
+        geometry.room_width,
+        geometry.room_height,
+    );
+    GameSession::new(map, SEED, geometry)
+}
+
+/// Hold the direction for the given time.
+fn walk(direction: ExitDirection, duration: f32) -> InputStep {
+    let (move_x, move_y) = match direction {
+        ExitDirection::North => (0.0, 1.0),
+        ExitDirection::South => (0.0, -1.0),
+        ExitDirection::East => (1.0, 0.0),
+        ExitDirection::West => (-1.0, 0.0),
+    };
+    InputStep {
+        duration,
+        move_x,
+        move_y,
+        ..InputStep::default()
+    }
+}
+
+#[test]
+fn walking_through_an_exit_changes_the_room() {
+    let mut session = start_session();
+    let start = session.room_coordinate;
+    let room = session.map.get_room(start).unwrap().clone();
+    let exit = room.exits()[0];
+    let (direction, dest_room) = match room.get_field(exit.0, exit.1) {
+        Some(RoomField::Exit(dest_room)) => (
+            ExitDirection::from_border_field(room.width, room.height, exit).unwrap(),
+            dest_room,
+        ),
+        field => panic!("Expected an exit at {:?}, found {:?}", exit, field),
+    };
+    // Start right in front of the exit.
+    session.spawn_player = Some(session.geometry.tile_to_pixel(room.entry_point(exit).unwrap()));
+
+    let mut game = HeadlessGame::new(session, app_root(), TIMESTEP).unwrap();
+    game.play_script(InputScript {
+        steps: vec![walk(direction, 0.5)],
+    });
+    game.run_script();
+
+    assert_ne!(game.room_coordinate(), start);
+    assert_eq!(game.room_coordinate(), dest_room.to_absolute_coordinates(start));
+    assert_eq!(game.deaths, 0);
+}
+
+#[test]
+fn attacking_right_destroys_the_bush() {
+    let mut session = start_session();
+    let start = session.room_coordinate;
+    let y = session.geometry.room_height / 2 + 1;
+    let bush = (6, y);
+    // A free row with the player on the left and a weak bush in front.
+    let room = session.map.get_room_mut(start).unwrap();
+    for x in 1..room.width - 1 {
+        room.set_field(x, y, RoomField::Nothing);
+    }
+    room.set_field(bush.0, bush.1, RoomField::Bush);
+    let mut state = RoomState::default();
+    state.health.push((bush, 0.5));
+    session.room_states.add_room(start, state);
+    session.spawn_player = Some(session.geometry.tile_to_pixel((2, y)));
+
+    let script = InputScript::load(app_root().join("resources/scripts/attack_right.ron")).unwrap();
+    let mut game = HeadlessGame::new(session, app_root(), TIMESTEP).unwrap();
+    game.play_script(script);
+    game.run_script();
+    game.session.store_room_state(&mut game.world);
+
+    assert_eq!(game.room_coordinate(), start);
+    let state = game.session.room_states.get_room(start).unwrap();
+    assert!(state.is_removed(bush));
+}
+
+#[test]
+fn replay_reproduces_the_run() {
+    let script = InputScript {
+        steps: vec![
+            walk(ExitDirection::West, 1.0),
+            InputStep { duration: 0.1, attack: true, ..InputStep::default() },
+            walk(ExitDirection::North, 0.6),
+            walk(ExitDirection::West, 1.5),
+            InputStep { duration: 0.1, attack: true, ..InputStep::default() },
+            walk(ExitDirection::South, 0.5),
+        ],
+    };
+    let mut game = HeadlessGame::new(start_session(), app_root(), TIMESTEP).unwrap();
+    game.play_script(script);
+    game.run_script();
+    game.session.store_room_state(&mut game.world);
+
+    let mut replayed = HeadlessGame::from_replay(game.replay(), app_root()).unwrap();
+    replayed.run_script();
+    replayed.session.store_room_state(&mut replayed.world);
+
+    assert_eq!(replayed.room_coordinate(), game.room_coordinate());
+    assert_eq!(replayed.player_position(), game.player_position());
+    assert_eq!(replayed.session.room_states, game.session.room_states);
+    assert_eq!(replayed.deaths, game.deaths);
+}